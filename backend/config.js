@@ -21,6 +21,9 @@ export const CONFIG = {
     API_PORT: parseInt(process.env.API_PORT) || 3000,
     BATCH_ID: process.env.BATCH_ID || 'batch3',
     INIT_BATCH_ID: process.env.INIT_BATCH_ID || 'batch2',
+    // Catalog product new batches are created against
+    PRODUCT_SKU: process.env.PRODUCT_SKU || 'FRESH-FISH',
+    METADATA_SCHEMA_VERSION: parseInt(process.env.METADATA_SCHEMA_VERSION) || 1,
     
     // IoT Thresholds
     MAX_TEMP: parseFloat(process.env.MAX_TEMP) || 4.0,
@@ -19,7 +19,8 @@ const ipfs = create({
     protocol: 'http'
 });
 
-let discoveredBatches = new Set();
+// Batch account address -> { batchId, producer }
+let discoveredBatches = new Map();
 let lastScanTime = 0;
 const SCAN_INTERVAL = 60000;
 
@@ -51,7 +52,7 @@ async function discoverBatches() {
 
         console.log(`[Gateway] Found ${accounts.length} batch accounts`);
 
-        const newBatches = new Set();
+        const newBatches = new Map();
         
         for (const account of accounts) {
             try {
@@ -65,10 +66,15 @@ async function discoverBatches() {
                 
                 if (data.length < offset + batchIdLen) continue;
                 const batchId = data.slice(offset, offset + batchIdLen).toString('utf8');
+                offset += batchIdLen;
+
+                // Batch PDAs are namespaced by producer, which follows the ID
+                if (data.length < offset + 32) continue;
+                const producer = new PublicKey(data.slice(offset, offset + 32)).toBase58();
                 
                 // Validate batch ID
                 if (batchId && batchId.length > 0 && batchId.length <= 64) {
-                    newBatches.add(batchId);
+                    newBatches.set(account.pubkey.toBase58(), { batchId, producer });
                     console.log(`[Gateway]   ✓ Discovered batch: ${batchId}`);
                 }
             } catch (e) {
@@ -76,7 +82,7 @@ async function discoverBatches() {
             }
         }
 
-        const addedCount = [...newBatches].filter(b => !discoveredBatches.has(b)).length;
+        const addedCount = [...newBatches.keys()].filter(b => !discoveredBatches.has(b)).length;
         if (addedCount > 0) {
             console.log(`[Gateway] 🎉 Discovered ${addedCount} new batches`);
         }
@@ -180,9 +186,9 @@ async function startSimulation() {
             }
 
             // Select random batch
-            const batchArray = Array.from(discoveredBatches);
+            const batchArray = Array.from(discoveredBatches.values());
             const randomIndex = Math.floor(Math.random() * batchArray.length);
-            const batchId = batchArray[randomIndex];
+            const { batchId, producer } = batchArray[randomIndex];
             
             console.log(`\n[Gateway] ━━━ Simulating for batch: ${batchId} ━━━`);
 
@@ -209,6 +215,7 @@ async function startSimulation() {
                 cid: cid,
                 hash: hash,
                 batchId: batchId,
+                producer: producer,
                 timestamp: batch.timestamp,
                 summary: summary
             };
@@ -3,22 +3,20 @@ import mqtt from 'mqtt';
 import { create } from 'ipfs-http-client';
 import { MerkleTree } from 'merkletreejs';
 import CryptoJS from 'crypto-js';
-import {
-    Connection,
-    PublicKey,
-    Keypair,
-    SystemProgram,
-    Transaction,
-    TransactionInstruction
-} from '@solana/web3.js';
-import fs from 'fs';
-import path from 'path';
-import { fileURLToPath } from 'url';
+import { PublicKey } from '@solana/web3.js';
 import { CONFIG } from './config.js';
-
-// Define __filename and __dirname manually in ESM
-const __filename = fileURLToPath(import.meta.url);
-const __dirname = path.dirname(__filename);
+import {
+    BN,
+    connection,
+    deriveBatchPDA,
+    deriveRoutePDA,
+    ensureFunding,
+    ensureSystemConfig,
+    loadKeypair,
+    programFor,
+    toBps,
+    toCentiC
+} from './solana.js';
 
 // Thresholds for breach detection
 const THRESHOLDS = {
@@ -28,17 +26,11 @@ const THRESHOLDS = {
 };
 
 // Load keypairs
-const loadKeypair = (file) => {
-    const secret = JSON.parse(fs.readFileSync(file, 'utf-8'));
-    return Keypair.fromSecretKey(new Uint8Array(secret));
-};
-
-const oracleKeypair = loadKeypair(path.join(__dirname, 'oracle-keypair.json'));
-const adminKeypair = loadKeypair(path.join(__dirname, 'admin-keypair.json'));
+const oracleKeypair = loadKeypair('oracle-keypair.json');
+const adminKeypair = loadKeypair('admin-keypair.json');
 
 // Solana setup
-const connection = new Connection(CONFIG.SOLANA_RPC_URL, 'confirmed');
-const programId = new PublicKey(CONFIG.PROGRAM_ID);
+const program = programFor(oracleKeypair);
 
 // MQTT client
 const mqttClient = mqtt.connect(CONFIG.MQTT_BROKER_URL, {
@@ -87,179 +79,22 @@ const checkThresholds = (readings) => {
     return { breachDetected, breachCount, breaches };
 };
 
-// PDA derivation
-const deriveBatchPDA = (batchId) => PublicKey.findProgramAddressSync([Buffer.from('batch'), Buffer.from(batchId)], programId)[0];
-const deriveConfigPDA = () => PublicKey.findProgramAddressSync([Buffer.from('config')], programId)[0];
-
-// Build raw instruction for initialize_system_config
-const buildInitializeConfigIx = (adminPubkey, oraclePubkey, configPda) => {
-    const discriminator = Uint8Array.from([38, 75, 134, 154, 249, 64, 246, 46]);
-    const data = Buffer.concat([
-        Buffer.from(discriminator),
-        Buffer.from(adminPubkey.toBytes()),
-        Buffer.from(oraclePubkey.toBytes())
-    ]);
-    const keys = [
-        { pubkey: configPda, isSigner: false, isWritable: true },
-        { pubkey: adminPubkey, isSigner: true, isWritable: true },
-        { pubkey: SystemProgram.programId, isSigner: false, isWritable: false }
-    ];
-    return new TransactionInstruction({ keys, programId, data });
-};
-
-// Ensure funding for wallets
-const ensureFunding = async () => {
-    const minSol = 1 * 1e9; // 1 SOL
-    const balAdmin = await connection.getBalance(adminKeypair.publicKey).catch(() => 0);
-    if (balAdmin < minSol) {
-        const sig = await connection.requestAirdrop(adminKeypair.publicKey, 10 * 1e9);
-        await connection.confirmTransaction(sig);
-        console.log('[Oracle] Admin wallet funded');
-    }
-    const balOracle = await connection.getBalance(oracleKeypair.publicKey).catch(() => 0);
-    if (balOracle < minSol) {
-        const sig = await connection.requestAirdrop(oracleKeypair.publicKey, 10 * 1e9);
-        await connection.confirmTransaction(sig);
-        console.log('[Oracle] Oracle wallet funded');
-    }
-};
-
-// Ensure system config exists
-const initializeSystemIfNeeded = async () => {
-    const configPda = deriveConfigPDA();
-    const ai = await connection.getAccountInfo(configPda);
-    if (ai) {
-        console.log(`[Oracle] system_config exists: ${configPda.toString()}`);
-        return;
-    }
-    console.log('[Oracle] Initializing system_config...');
-    const ix = buildInitializeConfigIx(adminKeypair.publicKey, oracleKeypair.publicKey, configPda);
-    const tx = new Transaction().add(ix);
-    tx.feePayer = adminKeypair.publicKey;
-    const sig = await connection.sendTransaction(tx, [adminKeypair], { preflightCommitment: 'confirmed' });
-    await connection.confirmTransaction(sig, 'confirmed');
-    console.log('[Oracle] system_config initialized:', sig);
-};
-
-// CORRECTED: Function to check if batch exists
-const checkBatchExists = async (batchPDA) => {
+// Fetch the batch account, or null if it does not exist
+const fetchBatch = async (batchPDA) => {
     try {
-        const accountInfo = await connection.getAccountInfo(batchPDA);
-        if (!accountInfo) {
+        const batch = await program.account.batch.fetchNullable(batchPDA);
+        if (!batch) {
             console.log(`[Oracle] Batch account does not exist: ${batchPDA.toString()}`);
-            return false;
+            return null;
         }
         console.log(`[Oracle] Batch account exists: ${batchPDA.toString()}`);
-        return true;
+        return batch;
     } catch (e) {
         console.log(`[Oracle] Error checking batch existence: ${e.message}`);
-        return false;
+        return null;
     }
 };
 
-// CORRECTED: Function to read batch timestamp from on-chain account
-const getBatchTimestamp = async (batchPDA) => {
-    try {
-        const accountInfo = await connection.getAccountInfo(batchPDA);
-        if (!accountInfo || !accountInfo.data) {
-            return 0;
-        }
-        
-        const data = accountInfo.data;
-        let offset = 8; // Skip 8-byte discriminator
-        
-        // Skip batch_id (string)
-        const batchIdLen = data.readUInt32LE(offset);
-        offset += 4 + batchIdLen;
-        
-        // Skip producer (32 bytes)
-        offset += 32;
-        
-        // Skip current_owner (32 bytes)  
-        offset += 32;
-        
-        // Skip status (1 byte)
-        offset += 1;
-        
-        // Skip origin_details
-        // production_date (8) + quantity (8) + weight (8) + product_type (string)
-        offset += 8 + 8 + 8;
-        const productTypeLen = data.readUInt32LE(offset);
-        offset += 4 + productTypeLen;
-        
-        // Skip metadata_hash (32 bytes)
-        offset += 32;
-        
-        // Skip metadata_cid (string)
-        const metadataCidLen = data.readUInt32LE(offset);
-        offset += 4 + metadataCidLen;
-        
-        // Skip events vector (4 bytes for length + variable data)
-        const eventsLen = data.readUInt32LE(offset);
-        offset += 4;
-        // Skip each event (we don't need to parse events, just skip them)
-        for (let i = 0; i < eventsLen; i++) {
-            // Skip event_type (1 byte) + timestamp (8) + from_wallet (32) + to_wallet (32) 
-            // + details_hash (32) + details_cid (string)
-            offset += 1 + 8 + 32 + 32 + 32;
-            const detailsCidLen = data.readUInt32LE(offset);
-            offset += 4 + detailsCidLen;
-        }
-        
-        // Now we're at iot_summary
-        // Read timestamp (first 8 bytes of IoTSummaryStruct)
-        const timestamp = Number(data.readBigInt64LE(offset));
-        console.log(`[Oracle] Successfully read batch timestamp: ${timestamp}`);
-        return timestamp;
-        
-    } catch (e) {
-        console.log(`[Oracle] Could not read batch timestamp: ${e.message}`);
-        console.log(`[Oracle] Defaulting timestamp to 0`);
-        return 0;
-    }
-};
-
-// Build raw instruction for update_iot_summary
-const buildUpdateIotSummaryIx = (batchPda, oraclePubkey, systemConfigPda, summarySnake, hashBytes, cid) => {
-    const discriminator = Uint8Array.from([73, 239, 117, 188, 144, 71, 40, 16]);
-    const timestampBuf = Buffer.alloc(8);
-    timestampBuf.writeBigInt64LE(BigInt(summarySnake.timestamp), 0);
-    const minTempBuf = Buffer.alloc(4);
-    minTempBuf.writeFloatLE(summarySnake.min_temp, 0);
-    const maxTempBuf = Buffer.alloc(4);
-    maxTempBuf.writeFloatLE(summarySnake.max_temp, 0);
-    const avgTempBuf = Buffer.alloc(4);
-    avgTempBuf.writeFloatLE(summarySnake.avg_temp, 0);
-    const minHumidityBuf = Buffer.alloc(4);
-    minHumidityBuf.writeFloatLE(summarySnake.min_humidity, 0);
-    const maxHumidityBuf = Buffer.alloc(4);
-    maxHumidityBuf.writeFloatLE(summarySnake.max_humidity, 0);
-    const avgHumidityBuf = Buffer.alloc(4);
-    avgHumidityBuf.writeFloatLE(summarySnake.avg_humidity, 0);
-    const locationSummaryLen = Buffer.alloc(4);
-    locationSummaryLen.writeUInt32LE(summarySnake.location_summary.length, 0);
-    const locationSummaryBuf = Buffer.from(summarySnake.location_summary);
-    const breachDetectedBuf = Buffer.from([summarySnake.breach_detected ? 1 : 0]);
-    const breachCountBuf = Buffer.alloc(4);
-    breachCountBuf.writeUInt32LE(summarySnake.breach_count, 0);
-    const summaryBuf = Buffer.concat([
-        timestampBuf, minTempBuf, maxTempBuf, avgTempBuf,
-        minHumidityBuf, maxHumidityBuf, avgHumidityBuf,
-        locationSummaryLen, locationSummaryBuf, breachDetectedBuf, breachCountBuf
-    ]);
-    const hashBuf = Buffer.from(hashBytes);
-    const cidLen = Buffer.alloc(4);
-    cidLen.writeUInt32LE(cid.length, 0);
-    const cidBuf = Buffer.from(cid);
-    const data = Buffer.concat([Buffer.from(discriminator), summaryBuf, hashBuf, cidLen, cidBuf]);
-    const keys = [
-        { pubkey: batchPda, isSigner: false, isWritable: true },
-        { pubkey: oraclePubkey, isSigner: true, isWritable: true },
-        { pubkey: systemConfigPda, isSigner: false, isWritable: false }
-    ];
-    return new TransactionInstruction({ keys, programId, data });
-};
-
 // Throttle state per batch
 const batchState = new Map();
 
@@ -269,10 +104,11 @@ const lastTimestamps = new Map();
 // Track batches that have too many events or don't exist
 const problematicBatches = new Set();
 
-// CORRECTED: Build summary with improved timestamp logic
+// CORRECTED: Build summary with improved timestamp logic. Readings arrive in
+// degrees Celsius and percent; the program takes centi-degrees and basis points.
 const buildIoTSummary = (readings, breachDetected, breachCount, batchId, onChainTimestamp) => {
-    const temps = readings.map(r => r.temperature);
-    const humidities = readings.map(r => r.humidity);
+    const temps = readings.map(r => toCentiC(r.temperature));
+    const humidities = readings.map(r => toBps(r.humidity));
     
     const currentTime = Math.floor(Date.now() / 1000);
     
@@ -292,16 +128,17 @@ const buildIoTSummary = (readings, breachDetected, breachCount, batchId, onChain
     console.log(`[Oracle] Timestamp logic: on-chain=${onChainTimestamp}, current=${currentTime}, final=${newTimestamp}`);
     
     return {
-        timestamp: newTimestamp,
-        min_temp: Math.min(...temps),
-        max_temp: Math.max(...temps),
-        avg_temp: temps.reduce((a, b) => a + b, 0) / temps.length,
-        min_humidity: Math.min(...humidities),
-        max_humidity: Math.max(...humidities),
-        avg_humidity: humidities.reduce((a, b) => a + b, 0) / humidities.length,
-        location_summary: 'Bangalore, IN',
-        breach_detected: breachDetected,
-        breach_count: breachCount
+        timestamp: new BN(newTimestamp),
+        minTempCentiC: Math.min(...temps),
+        maxTempCentiC: Math.max(...temps),
+        avgTempCentiC: Math.round(temps.reduce((a, b) => a + b, 0) / temps.length),
+        minHumidityBps: Math.min(...humidities),
+        maxHumidityBps: Math.max(...humidities),
+        avgHumidityBps: Math.round(humidities.reduce((a, b) => a + b, 0) / humidities.length),
+        locationSummary: 'Bangalore, IN',
+        breachDetected,
+        breachCount,
+        location: null
     };
 };
 
@@ -312,7 +149,7 @@ const wait = (ms) => new Promise(resolve => setTimeout(resolve, ms));
 const processIoTData = async (message) => {
     let batchId;
     try {
-        const { cid, batchId: msgBatchId, timestamp, summary } = message;
+        const { cid, batchId: msgBatchId, producer } = message;
         batchId = msgBatchId; // Store for error handling
         
         console.log(`[Oracle] Processing Batch: ${batchId}, CID: ${cid}`);
@@ -381,41 +218,34 @@ const processIoTData = async (message) => {
             return;
         }
 
-        const batchPDA = deriveBatchPDA(batchId);
-        const configPDA = deriveConfigPDA();
+        // Batch PDAs are namespaced by producer, so the gateway sends it along.
+        const batchPDA = deriveBatchPDA(new PublicKey(producer), batchId);
 
         // NEW: Check if batch exists before proceeding
-        const batchExists = await checkBatchExists(batchPDA);
-        if (!batchExists) {
+        const batch = await fetchBatch(batchPDA);
+        if (!batch) {
             console.log(`[Oracle] ❌ Batch ${batchId} does not exist on-chain. Skipping update.`);
             problematicBatches.add(batchId);
             return;
         }
 
-        // Fetch the current timestamp from the on-chain account data.
-        const currentBatchTimestamp = await getBatchTimestamp(batchPDA);
+        const currentBatchTimestamp = batch.iotSummary.timestamp.toNumber();
         console.log(`[Oracle] Current on-chain batch timestamp: ${currentBatchTimestamp}`);
 
-        // Safety check: if we can't read the timestamp properly, wait and retry later
-        if (currentBatchTimestamp === 0 && (batchState.get(batchId)?.sentCount || 0) > 0) {
-            console.log(`[Oracle] ⚠️  Timestamp read as 0 but we've sent updates before. Possible account data issue.`);
-            console.log(`[Oracle] Skipping this update to avoid timestamp conflict.`);
-            return;
-        }
-
         // Pass the on-chain timestamp to the summary builder.
-        const iotSummarySnake = buildIoTSummary(readings, breachDetected, breachCount, batchId, currentBatchTimestamp);
-
-        console.log(`[Oracle] Sending transaction for batch ${batchId} with new timestamp ${iotSummarySnake.timestamp}...`);
-        const ix = buildUpdateIotSummaryIx(batchPDA, oracleKeypair.publicKey, configPDA, iotSummarySnake, merkleRootBytes, cid);
-        const tx = new Transaction().add(ix);
-        tx.feePayer = oracleKeypair.publicKey;
-        
-        const sig = await connection.sendTransaction(tx, [oracleKeypair], {
-            preflightCommitment: 'confirmed',
-            skipPreflight: false
-        });
-        await connection.confirmTransaction(sig, 'confirmed');
+        const iotSummary = buildIoTSummary(readings, breachDetected, breachCount, batchId, currentBatchTimestamp);
+
+        console.log(`[Oracle] Sending transaction for batch ${batchId} with new timestamp ${iotSummary.timestamp}...`);
+        // The corridor must be supplied once the batch has one, so fixes are
+        // checked against it.
+        const sig = await program.methods
+            .updateIotSummary(iotSummary, merkleRootBytes, cid)
+            .accounts({
+                batch: batchPDA,
+                route: batch.routeDefined ? deriveRoutePDA(batchPDA) : null,
+                oracle: oracleKeypair.publicKey
+            })
+            .rpc();
         console.log(`[Oracle] ✅ Transaction successful: ${sig}`);
         
         // Update state
@@ -429,7 +259,7 @@ const processIoTData = async (message) => {
         }
         
         // Handle specific errors
-        if (error.message.includes('InvalidTimestamp') || error.message.includes('0x177f')) {
+        if (error.message.includes('InvalidTimestamp')) {
             console.log(`[Oracle] Timestamp conflict detected for batch ${batchId}. Waiting before retry...`);
             
             // Reset the local timestamp to force a fresh read next time
@@ -440,13 +270,13 @@ const processIoTData = async (message) => {
             return;
         }
         
-        if (error.message.includes('TooManyEvents') || error.message.includes('0x177d')) {
+        if (error.message.includes('TooManyEvents')) {
             console.log(`[Oracle] 🚨 Too many events in batch ${batchId}. Marking as problematic.`);
             problematicBatches.add(batchId);
             return;
         }
         
-        if (error.message.includes('AccountNotInitialized') || error.message.includes('0xbc4')) {
+        if (error.message.includes('AccountNotInitialized')) {
             console.log(`[Oracle] 🚨 Batch ${batchId} not initialized. Marking as problematic.`);
             problematicBatches.add(batchId);
             return;
@@ -493,8 +323,8 @@ const initialize = async () => {
     console.log('[Oracle] Starting Oracle Service...');
     try {
         console.log('[Oracle] Connected to Solana:', await connection.getVersion());
-        await ensureFunding();
-        await initializeSystemIfNeeded();
+        await ensureFunding('Oracle', { Admin: adminKeypair, Oracle: oracleKeypair });
+        await ensureSystemConfig('Oracle', adminKeypair, oracleKeypair);
         console.log('[Oracle] Ready to process IoT data...');
     } catch (err) {
         console.error('[Oracle] Initialization error:', err);
@@ -0,0 +1,192 @@
+// backend/solana.js - Anchor client and helpers shared by the oracle and setup scripts
+import anchor from '@coral-xyz/anchor';
+import { Connection, Keypair, PublicKey } from '@solana/web3.js';
+import { createHash } from 'crypto';
+import fs from 'fs';
+import path from 'path';
+import { fileURLToPath } from 'url';
+import { CONFIG } from './config.js';
+
+const { AnchorProvider, BN, Program, Wallet } = anchor;
+export { BN };
+
+const __filename = fileURLToPath(import.meta.url);
+const __dirname = path.dirname(__filename);
+
+// IDL generated from contracts/programs/contracts; regenerate it whenever the
+// program's instructions or accounts change.
+const idl = JSON.parse(fs.readFileSync(path.join(__dirname, 'idl.json'), 'utf-8'));
+
+export const connection = new Connection(CONFIG.SOLANA_RPC_URL, 'confirmed');
+export const programId = new PublicKey(CONFIG.PROGRAM_ID);
+
+export const loadKeypair = (file) => {
+    const secret = JSON.parse(fs.readFileSync(path.join(__dirname, file), 'utf-8'));
+    return Keypair.fromSecretKey(new Uint8Array(secret));
+};
+
+// Program client whose provider wallet (fee payer) is `keypair`.
+export const programFor = (keypair) => {
+    const provider = new AnchorProvider(connection, new Wallet(keypair), { commitment: 'confirmed' });
+    return new Program({ ...idl, address: CONFIG.PROGRAM_ID }, provider);
+};
+
+// PDA derivation
+const findPda = (...seeds) => PublicKey.findProgramAddressSync(seeds, programId)[0];
+export const deriveConfigPDA = () => findPda(Buffer.from('config'));
+export const deriveUserPDA = (wallet) => findPda(Buffer.from('user'), wallet.toBuffer());
+export const deriveProductPDA = (sku) => findPda(Buffer.from('product'), Buffer.from(sku));
+export const deriveBatchPDA = (producer, batchId) => findPda(Buffer.from('batch'), producer.toBuffer(), Buffer.from(batchId));
+export const deriveRoutePDA = (batchPda) => findPda(Buffer.from('route'), batchPda.toBuffer());
+
+// On-chain units: temperatures in centi-degrees Celsius (i32), humidity in
+// basis points (u16, 10000 = 100%) and weights in whole grams (u64).
+export const toCentiC = (celsius) => Math.round(celsius * 100);
+export const toBps = (percent) => Math.min(10_000, Math.max(0, Math.round(percent * 100)));
+export const toGrams = (kilograms) => new BN(Math.round(kilograms * 1000));
+
+const BASE32_ALPHABET = 'abcdefghijklmnopqrstuvwxyz234567';
+
+const encodeBase32 = (bytes) => {
+    let out = '';
+    let buffer = 0;
+    let bits = 0;
+    for (const byte of bytes) {
+        buffer = (buffer << 8) | byte;
+        bits += 8;
+        while (bits >= 5) {
+            bits -= 5;
+            out += BASE32_ALPHABET[(buffer >> bits) & 31];
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    if (bits > 0) out += BASE32_ALPHABET[(buffer << (5 - bits)) & 31];
+    return out;
+};
+
+// SHA-256 of the JSON document together with its raw-codec CIDv1, the form
+// the program stores CIDs in.
+export const hashDocument = (doc) => {
+    const digest = createHash('sha256').update(JSON.stringify(doc)).digest();
+    const cid = 'b' + encodeBase32(Buffer.concat([Buffer.from([0x01, 0x55, 0x12, 0x20]), digest]));
+    return { hash: [...digest], cid };
+};
+
+export const ensureFunding = async (tag, keypairs) => {
+    const minSol = 1 * 1e9; // 1 SOL
+    for (const [name, keypair] of Object.entries(keypairs)) {
+        const balance = await connection.getBalance(keypair.publicKey).catch(() => 0);
+        if (balance < minSol) {
+            const sig = await connection.requestAirdrop(keypair.publicKey, 10 * 1e9);
+            await connection.confirmTransaction(sig);
+            console.log(`[${tag}] ${name} wallet funded`);
+        }
+    }
+};
+
+export const ensureSystemConfig = async (tag, adminKeypair, oracleKeypair) => {
+    const configPda = deriveConfigPDA();
+    if (await connection.getAccountInfo(configPda)) {
+        console.log(`[${tag}] system_config exists: ${configPda.toString()}`);
+        return;
+    }
+    console.log(`[${tag}] Initializing system_config...`);
+    const sig = await programFor(adminKeypair).methods
+        .intializeConfig(adminKeypair.publicKey, oracleKeypair.publicKey)
+        .accounts({ payer: adminKeypair.publicKey })
+        .rpc();
+    console.log(`[${tag}] system_config initialized:`, sig);
+};
+
+// Registers `producerKeypair` and has the admin approve it as a Producer.
+export const ensureApprovedProducer = async (tag, producerKeypair, adminKeypair) => {
+    const program = programFor(producerKeypair);
+    const userPda = deriveUserPDA(producerKeypair.publicKey);
+    let profile = await program.account.userProfile.fetchNullable(userPda);
+    if (!profile) {
+        console.log(`[${tag}] Creating user_profile...`);
+        const { hash } = hashDocument({ name: 'Test Producer', location: 'Bangalore, IN', certifications: [], role: 'Producer' });
+        const sig = await program.methods
+            .registerUser(hash)
+            .accounts({ user: producerKeypair.publicKey })
+            .rpc();
+        console.log(`[${tag}] user_profile created:`, sig);
+        profile = await program.account.userProfile.fetch(userPda);
+    }
+    if (profile.isApproved) {
+        console.log(`[${tag}] user_profile already approved: ${userPda.toString()}`);
+        return;
+    }
+    console.log(`[${tag}] Approving user as Producer...`);
+    const sig = await programFor(adminKeypair).methods
+        .approveUser({ producer: {} })
+        .accounts({ admin: adminKeypair.publicKey, userProfile: userPda })
+        .rpc();
+    console.log(`[${tag}] User approved as Producer:`, sig);
+};
+
+// Product registration is admin-only; the producer becomes its brand owner.
+export const ensureProduct = async (tag, adminKeypair, brandOwner) => {
+    const productPda = deriveProductPDA(CONFIG.PRODUCT_SKU);
+    if (await connection.getAccountInfo(productPda)) {
+        console.log(`[${tag}] product ${CONFIG.PRODUCT_SKU} exists: ${productPda.toString()}`);
+        return productPda;
+    }
+    console.log(`[${tag}] Registering product ${CONFIG.PRODUCT_SKU}...`);
+    const sig = await programFor(adminKeypair).methods
+        .registerProduct(CONFIG.PRODUCT_SKU, {
+            name: 'Fresh Fish',
+            category: 'Seafood',
+            brandOwner,
+            defaultThreshold: {
+                maxTempCentiC: toCentiC(CONFIG.MAX_TEMP),
+                maxHumidityBps: 10_000,
+                maxBreachDuration: CONFIG.MAX_BREACH_DURATION,
+            },
+            shelfLifeSeconds: new BN(0),
+            degradation: [],
+        })
+        .accounts({ admin: adminKeypair.publicKey })
+        .rpc();
+    console.log(`[${tag}] product registered:`, sig);
+    return productPda;
+};
+
+// Creates `batchId` for the producer against the configured product unless
+// it already exists. Returns the batch PDA.
+export const ensureBatch = async (tag, producerKeypair, batchId) => {
+    const batchPda = deriveBatchPDA(producerKeypair.publicKey, batchId);
+    if (await connection.getAccountInfo(batchPda)) {
+        console.log(`[${tag}] Batch ${batchId} already exists at ${batchPda.toString()}`);
+        return batchPda;
+    }
+    console.log(`[${tag}] Creating ${batchId}...`);
+    const productPda = deriveProductPDA(CONFIG.PRODUCT_SKU);
+    const now = Math.floor(Date.now() / 1000);
+    const originDetails = {
+        productionDate: new BN(now),
+        expiryDate: new BN(now + 14 * 24 * 3600),
+        quantity: new BN(1000),
+        weightGrams: toGrams(500.5),
+        product: productPda,
+    };
+    const metadata = {
+        batchId,
+        producer: producerKeypair.publicKey.toString(),
+        createdAt: new Date().toISOString()
+    };
+    const { hash, cid } = hashDocument(metadata);
+    const sig = await programFor(producerKeypair).methods
+        .createBatch(batchId, originDetails, hash, cid, CONFIG.METADATA_SCHEMA_VERSION, null)
+        .accounts({
+            batch: batchPda,
+            product: productPda,
+            route: null,
+            user: producerKeypair.publicKey,
+        })
+        .rpc();
+    console.log(`✅ ${batchId} created successfully!`);
+    console.log(`Transaction: ${sig}`);
+    console.log(`Batch PDA: ${batchPda.toString()}`);
+    return batchPda;
+};
@@ -0,0 +1,199 @@
+// Syntax checks for IPFS content identifiers. A CIDv0 is a bare base58btc
+// sha2-256 multihash ("Qm..."); a CIDv1 is a multibase prefix followed by the
+// varints <version><codec><hash function><digest length> and the digest.
+// Only the encoding is checked; whether the content exists is off-chain.
+// CIDv1s are stored re-encoded as lower-case base32, so storage limits and
+// prefix policies see one form however the client spelled the CID.
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const BASE32_ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz234567";
+const CID_V0_LENGTH: usize = 46;
+const SHA2_256: u64 = 0x12;
+const SHA2_256_LENGTH: u64 = 32;
+const MAX_DIGEST_LENGTH: u64 = 64;
+
+/// Returns the CID in canonical form (CIDv0 unchanged, CIDv1 as "b" plus
+/// lower-case base32), or `None` if it is not a well-formed CID.
+pub fn canonical_cid(cid: &str) -> Option<String> {
+    if cid.len() == CID_V0_LENGTH && cid.starts_with("Qm") {
+        return decode_base58(cid.as_bytes())
+            .filter(|bytes| bytes.len() == 34 && bytes[0] == SHA2_256 as u8 && bytes[1] == SHA2_256_LENGTH as u8)
+            .map(|_| cid.to_string());
+    }
+
+    let (&prefix, encoded) = cid.as_bytes().split_first()?;
+    let decoded = match prefix {
+        b'b' => decode_base32(encoded),
+        b'B' => decode_base32(&encoded.to_ascii_lowercase()),
+        b'z' => decode_base58(encoded),
+        b'f' => decode_base16(encoded),
+        b'F' => decode_base16(&encoded.to_ascii_lowercase()),
+        _ => None,
+    };
+    decoded
+        .filter(|bytes| valid_cid_v1(bytes))
+        .map(|bytes| format!("b{}", encode_base32(&bytes)))
+}
+
+fn valid_cid_v1(mut bytes: &[u8]) -> bool {
+    let version = read_varint(&mut bytes);
+    let codec = read_varint(&mut bytes);
+    let hash = read_varint(&mut bytes);
+    let length = read_varint(&mut bytes);
+    match (version, codec, hash, length) {
+        (Some(1), Some(_), Some(hash), Some(length)) => {
+            length > 0
+                && length <= MAX_DIGEST_LENGTH
+                && bytes.len() as u64 == length
+                && (hash != SHA2_256 || length == SHA2_256_LENGTH)
+        }
+        _ => false,
+    }
+}
+
+// Unsigned LEB128 as used by multiformats, capped at 9 bytes.
+fn read_varint(bytes: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for i in 0..9 {
+        let (&byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn decode_base58(input: &[u8]) -> Option<Vec<u8>> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(input.len());
+    for &c in input {
+        let mut carry = BASE58_ALPHABET.iter().position(|&a| a == c)? as u32;
+        for byte in bytes.iter_mut() {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    // Each leading '1' encodes a leading zero byte.
+    bytes.extend(input.iter().take_while(|&&c| c == b'1').map(|_| 0));
+    bytes.reverse();
+    Some(bytes)
+}
+
+// RFC 4648 base32, lower case and unpadded.
+fn decode_base32(input: &[u8]) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(input.len() * 5 / 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for &c in input {
+        buffer = (buffer << 5) | BASE32_ALPHABET.iter().position(|&a| a == c)? as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    // Leftover bits are padding and must be zero.
+    (bits < 5 && buffer == 0).then_some(bytes)
+}
+
+fn encode_base32(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[(buffer >> bits) as usize & 31] as char);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[(buffer << (5 - bits)) as usize & 31] as char);
+    }
+    encoded
+}
+
+#[allow(clippy::manual_is_multiple_of)]
+fn decode_base16(input: &[u8]) -> Option<Vec<u8>> {
+    if input.len() % 2 != 0 {
+        return None;
+    }
+    input
+        .chunks(2)
+        .map(|pair| {
+            let hex = std::str::from_utf8(pair).ok()?;
+            if hex.bytes().any(|b| b.is_ascii_uppercase()) {
+                return None;
+            }
+            u8::from_str_radix(hex, 16).ok()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The raw-codec CIDv1 of "hello world" in each supported multibase.
+    const HELLO_BASE32: &str = "bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e";
+    const HELLO_VARIANTS: [&str; 5] = [
+        HELLO_BASE32,
+        "BAFKREIFZJUT3TE2NHYEKKLSS27NH3K72YSCO7Y32KOAO5EEI66WOF36N5E",
+        "zb2rhj7crUKTQYRGCRATFaQ6YFLTde2YzdqbbhAASkL9uRDXn",
+        "f01551220b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+        "F01551220B94D27B9934D3E08A52E52D7DA7DABFAC484EFE37A5380EE9088F7ACE2EFCDE9",
+    ];
+
+    #[test]
+    fn keeps_cid_v0_as_is() {
+        let cid = "QmaozNR7DZHQK1ZcU9p7QdrshMvXqWK6gpu5rmrkPdT3L4";
+        assert_eq!(canonical_cid(cid).as_deref(), Some(cid));
+    }
+
+    #[test]
+    fn canonicalizes_every_cid_v1_multibase_to_base32() {
+        for cid in HELLO_VARIANTS {
+            assert_eq!(canonical_cid(cid).as_deref(), Some(HELLO_BASE32), "{cid}");
+        }
+    }
+
+    #[test]
+    fn rejects_digests_of_the_wrong_length() {
+        // sha2-256 declared with a 31-byte digest.
+        assert_eq!(canonical_cid("bafkreh5zjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n"), None);
+        // 32 bytes declared, 31 present.
+        assert_eq!(canonical_cid("bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n"), None);
+        // Base16 form with the last digest byte dropped.
+        assert_eq!(canonical_cid("f01551220b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcd"), None);
+    }
+
+    #[test]
+    fn rejects_non_zero_trailing_base32_bits() {
+        assert_eq!(canonical_cid("bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5f"), None);
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        for cid in [
+            "",
+            "b",
+            "Qm",
+            "hello world",
+            "QmaozNR7DZHQK1ZcU9p7QdrshMvXqWK6gpu5rmrkPdT3L0",
+            "xafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e",
+            "f01551220b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde",
+            "f01551220B94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+        ] {
+            assert_eq!(canonical_cid(cid), None, "{cid}");
+        }
+    }
+}
@@ -16,6 +16,10 @@ pub enum CustomError {
     AlreadyApproved,
     #[msg("Invalid wallet address")]
     InvalidWallet,
+    #[msg("Invalid policy parameters")]
+    InvalidPolicy,
+    #[msg("Policy was updated since it was read")]
+    PolicyVersionMismatch,
 }
 
 #[error_code]
@@ -42,8 +46,6 @@ pub enum SupplyChainError {
     InvalidDetailsHash,
     #[msg("Invalid details CID")]
     InvalidDetailsCid,
-    #[msg("Invalid role for handover")]
-    InvalidHandoverRole,
     #[msg("Invalid role transition")]
     InvalidRoleTransition,
     #[msg("Too many events in batch")]
@@ -52,7 +54,7 @@ pub enum SupplyChainError {
     UnauthorizedOracle,
     #[msg("Invalid timestamp - must be greater than previous")]
     InvalidTimestamp,
-    #[msg("Invalid temperature range - min_temp must be <= max_temp")]
+    #[msg("Invalid temperature range - min_temp_centi_c must be <= max_temp_centi_c")]
     InvalidTemperatureRange,
     #[msg("IoT data is too old for compliance check")]
     StaleIoTData,
@@ -62,4 +64,108 @@ pub enum SupplyChainError {
     BatchAlreadyRecalled,
     #[msg("Reason cannot be empty")]
     EmptyReason,
+    #[msg("Batch is not flagged")]
+    BatchNotFlagged,
+    #[msg("IoT summary has no breach to dispute")]
+    NoBreachToDispute,
+    #[msg("Dispute is not open")]
+    DisputeNotOpen,
+    #[msg("Caller is not the regulator assigned to this dispute")]
+    NotAssignedRegulator,
+    #[msg("Invalid quantity")]
+    InvalidQuantity,
+    #[msg("Batch is not available for sale")]
+    BatchNotForSale,
+    #[msg("Batch is already closed")]
+    BatchAlreadyClosed,
+    #[msg("Batch has not reached a terminal state")]
+    BatchNotTerminal,
+    #[msg("Batch ID belongs to an archived batch")]
+    BatchIdArchived,
+    #[msg("Temperature reading is outside the supported sensor range")]
+    InvalidTemperatureValue,
+    #[msg("Humidity reading must be between 0 and 10000 basis points")]
+    InvalidHumidityValue,
+    #[msg("Weight must be a positive number of grams")]
+    InvalidWeight,
+    #[msg("Average temperature must lie between min_temp_centi_c and max_temp_centi_c")]
+    AverageTemperatureOutOfRange,
+    #[msg("Invalid humidity range - min_humidity_bps must be <= max_humidity_bps")]
+    InvalidHumidityRange,
+    #[msg("Average humidity must lie between min_humidity_bps and max_humidity_bps")]
+    AverageHumidityOutOfRange,
+    #[msg("breach_count must be non-zero exactly when breach_detected is set")]
+    BreachCountMismatch,
+    #[msg("IoT summary timestamp is in the future")]
+    FutureTimestamp,
+    #[msg("Account is not a certification of this batch")]
+    InvalidCertificationAccount,
+    #[msg("Batch has reached the maximum number of handovers")]
+    TooManyHandovers,
+    #[msg("Consumer claim has already been redeemed")]
+    ClaimAlreadyRedeemed,
+    #[msg("Claim code does not match")]
+    InvalidClaimCode,
+    #[msg("Caller is neither the custodian nor a provider bound by them")]
+    NotCustodianOrProvider,
+    #[msg("Service provider must be an approved transporter, warehouse or processor")]
+    InvalidServiceProvider,
+    #[msg("Invalid location coordinates or accuracy")]
+    InvalidLocation,
+    #[msg("User is not the current custodian of the batch")]
+    NotCurrentCustodian,
+    #[msg("Route needs waypoints with a corridor width, a destination geofence, or both")]
+    InvalidRoute,
+    #[msg("Route can only be defined before the batch leaves the producer")]
+    RouteAlreadyUnderway,
+    #[msg("Batch has a route; its route account must be supplied")]
+    RouteAccountMissing,
+    #[msg("Batch already has a shipment in progress")]
+    ShipmentAlreadyOpen,
+    #[msg("Invalid shipment plan")]
+    InvalidShipmentPlan,
+    #[msg("Batch has a shipment in progress; its plan must be supplied")]
+    ShipmentPlanMissing,
+    #[msg("Receiver is not the one named in the shipment plan")]
+    UnexpectedReceiver,
+    #[msg("Shipment is not overdue")]
+    ShipmentNotOverdue,
+    #[msg("Invalid expiry date")]
+    InvalidExpiryDate,
+    #[msg("Batch is past its expiry date")]
+    BatchExpired,
+    #[msg("Batch has not expired yet")]
+    BatchNotExpired,
+    #[msg("Invalid product details")]
+    InvalidProduct,
+    #[msg("Product account does not match the batch origin details")]
+    ProductMismatch,
+    #[msg("Invalid GTIN or check digit")]
+    InvalidGtin,
+    #[msg("Invalid GS1 lot number")]
+    InvalidLotNumber,
+    #[msg("Invalid SSCC or check digit")]
+    InvalidSscc,
+    #[msg("Batch already has a GS1 lot number")]
+    LotAlreadyAssigned,
+    #[msg("Metadata schema version must be non-zero")]
+    InvalidMetadataSchemaVersion,
+    #[msg("IoT summary is not in the batch history")]
+    SummaryNotInHistory,
+    #[msg("Certification has already been revoked")]
+    CertificationAlreadyRevoked,
+    #[msg("Claim commitment must be made in an earlier slot than the reveal")]
+    ClaimCommitmentTooRecent,
+    #[msg("Only the product's brand owner may do this")]
+    NotBrandOwner,
+    #[msg("Account passed for archiving does not belong to this batch")]
+    InvalidArchiveAccount,
+    #[msg("Dispute is still open")]
+    DisputeStillOpen,
+    #[msg("Rent recipient for an archived account was not supplied")]
+    MissingRentRecipient,
+    #[msg("Account is not a commitment to this claim")]
+    InvalidClaimCommitment,
+    #[msg("Too many live batches share this id")]
+    BatchIdIndexFull,
 }
\ No newline at end of file
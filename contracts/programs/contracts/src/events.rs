@@ -1,6 +1,6 @@
 use anchor_lang::prelude::*;
 
-use crate::state::{Role, UserProfile};
+use crate::state::{BatchStatus, ClosureReason, EventType, GeoPoint, Role, ShipmentOutcome};
 
 #[event]
 
@@ -10,6 +10,41 @@ pub struct InitializeConfigEvent{
     pub oracle_wallet:Pubkey
 }
 
+#[event]
+pub struct PolicyUpdated{
+    pub config:Pubkey,
+    pub version:u32
+}
+
+#[event]
+pub struct RoleTransitionsUpdated{
+    pub from_role:Role,
+    pub allowed:u16
+}
+
+#[event]
+pub struct ProductUpdated{
+    pub product:Pubkey,
+    pub sku:String,
+    pub updated_by:Pubkey,
+    pub timestamp:i64
+}
+
+#[event]
+pub struct Gs1LotAssigned{
+    pub batch_id:String,
+    pub gtin:String,
+    pub lot:String
+}
+
+#[event]
+pub struct LogisticUnitRegistered{
+    pub batch_id:String,
+    pub sscc:String,
+    pub registered_by:Pubkey,
+    pub timestamp:i64
+}
+
 #[event]
 pub struct UserEvent{
     pub user_wallet:Pubkey,
@@ -24,10 +59,185 @@ pub struct BatchCreated {
     pub timestamp: i64,
 }
 
+#[event]
+pub struct OwnershipTransferred {
+    pub batch_id: String,
+    pub from_wallet: Pubkey,
+    pub to_wallet: Pubkey,
+    pub custodian: Pubkey,
+    pub timestamp: i64,
+}
+
 #[event]
 pub struct HandoverLogged {
     pub batch_id: String,
     pub from_wallet: Pubkey,
     pub to_wallet: Pubkey,
     pub timestamp: i64,
-}
\ No newline at end of file
+}
+#[event]
+pub struct FlagResolved {
+    pub batch_id: String,
+    pub regulator: Pubkey,
+    pub restored_status: BatchStatus,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FlagEscalated {
+    pub batch_id: String,
+    pub regulator: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DisputeOpened {
+    pub batch_id: String,
+    pub dispute: Pubkey,
+    pub opened_by: Pubkey,
+    pub regulator: Pubkey,
+    pub iot_hash: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DisputeRuled {
+    pub batch_id: String,
+    pub dispute: Pubkey,
+    pub regulator: Pubkey,
+    pub upheld: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SaleRecorded {
+    pub batch_id: String,
+    pub seller: Pubkey,
+    pub quantity: u64,
+    pub remaining_quantity: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ConsumerClaimIssued {
+    pub batch_id: String,
+    pub claim: Pubkey,
+    pub retailer: Pubkey,
+    pub quantity: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ConsumerClaimRedeemed {
+    pub batch_id: String,
+    pub claim: Pubkey,
+    pub consumer: Pubkey,
+    pub quantity: u64,
+    pub remaining_quantity: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ConsumerClaimCancelled {
+    pub batch_id: String,
+    pub claim: Pubkey,
+    pub retailer: Pubkey,
+    pub quantity: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BatchClosed {
+    pub batch_id: String,
+    pub closed_by: Pubkey,
+    pub reason: ClosureReason,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BatchArchived {
+    pub batch_id: String,
+    pub archive: Pubkey,
+    pub producer: Pubkey,
+    pub events_root: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BreachAttributed {
+    pub batch_id: String,
+    pub custodian: Pubkey,
+    pub leg_index: u16,
+    pub excursion_seconds: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ServiceProviderBound {
+    pub batch_id: String,
+    pub custodian: Pubkey,
+    pub provider: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CustodyUpdateLogged {
+    pub batch_id: String,
+    pub event_type: EventType,
+    pub actor: Pubkey,
+    pub custodian: Pubkey,
+    pub location: Option<GeoPoint>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RouteDeviated {
+    pub batch_id: String,
+    pub custodian: Pubkey,
+    pub location: GeoPoint,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ShipmentDispatched {
+    pub batch_id: String,
+    pub sender: Pubkey,
+    pub expected_receiver: Pubkey,
+    pub deadline: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ShipmentSettled {
+    pub batch_id: String,
+    pub outcome: ShipmentOutcome,
+    pub deadline: i64,
+    // Zero when settled by the overdue crank before delivery.
+    pub delivered_at: i64,
+}
+
+#[event]
+pub struct ShipmentCancelled {
+    pub batch_id: String,
+    pub cancelled_by: Pubkey,
+    pub expected_receiver: Pubkey,
+    pub outcome: ShipmentOutcome,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BatchExpired {
+    pub batch_id: String,
+    pub expiry_date: i64,
+    pub effective_expiry: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CertificationRevoked {
+    pub batch_id: String,
+    pub certification: Pubkey,
+    pub cert_type: String,
+    pub revoked_by: Pubkey,
+    pub timestamp: i64,
+}
@@ -0,0 +1,209 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::SupplyChainError, state::{GeoPoint, Geofence, RouteCorridor, Waypoint, MAX_GEO_ACCURACY_M}};
+
+const E7: i128 = 10_000_000;
+// Metres per degree of latitude (mean Earth radius 6 371 008.8 m).
+const METRES_PER_DEGREE: i128 = 111_195;
+const COS_SCALE: i128 = 1_000_000;
+
+pub fn valid_coordinates(lat_e7: i32, lon_e7: i32) -> bool {
+    (-90 * E7..=90 * E7).contains(&(lat_e7 as i128)) && (-180 * E7..=180 * E7).contains(&(lon_e7 as i128))
+}
+
+/// The coordinates of a fix, as stored in the batch event log.
+pub fn waypoint_of(point: &GeoPoint) -> Waypoint {
+    Waypoint {
+        lat_e7: point.lat_e7,
+        lon_e7: point.lon_e7,
+    }
+}
+
+pub fn validate_geo_point(point: &GeoPoint) -> Result<()> {
+    require!(
+        valid_coordinates(point.lat_e7, point.lon_e7),
+        SupplyChainError::InvalidLocation
+    );
+    require!(
+        point.accuracy_m <= MAX_GEO_ACCURACY_M && point.timestamp > 0,
+        SupplyChainError::InvalidLocation
+    );
+    Ok(())
+}
+
+/// Whether a reported location can lie on the agreed route: within
+/// `corridor_width_m` of the waypoint polyline, or inside the destination
+/// geofence. The point's own accuracy radius counts in its favour, so a fix
+/// is only a deviation when its whole uncertainty circle is outside.
+pub fn within_route(route: &RouteCorridor, point: &GeoPoint) -> bool {
+    let slack = point.accuracy_m as i128;
+
+    if let Some(destination) = &route.destination {
+        if within_geofence(destination, point, slack) {
+            return true;
+        }
+    }
+
+    let reach = route.corridor_width_m as i128 + slack;
+    let reach_sq = reach * reach;
+    match route.waypoints.as_slice() {
+        [] => false,
+        [only] => distance_sq_m(point, only.lat_e7, only.lon_e7) <= reach_sq,
+        waypoints => waypoints
+            .windows(2)
+            .any(|leg| segment_distance_sq_m(point, &leg[0], &leg[1]) <= reach_sq),
+    }
+}
+
+fn within_geofence(fence: &Geofence, point: &GeoPoint, slack: i128) -> bool {
+    let reach = fence.radius_m as i128 + slack;
+    distance_sq_m(point, fence.lat_e7, fence.lon_e7) <= reach * reach
+}
+
+// Equirectangular projection around `point`, in metres. Plenty accurate at the
+// corridor scales involved (tens of kilometres) away from the poles.
+fn project_m(point: &GeoPoint, lat_e7: i32, lon_e7: i32) -> (i128, i128) {
+    let mut dlon = lon_e7 as i128 - point.lon_e7 as i128;
+    if dlon > 180 * E7 {
+        dlon -= 360 * E7;
+    } else if dlon < -180 * E7 {
+        dlon += 360 * E7;
+    }
+    let dlat = lat_e7 as i128 - point.lat_e7 as i128;
+    let cos = cos_deg_scaled((lat_e7 as i128 + point.lat_e7 as i128) / 2);
+
+    let x = dlon * METRES_PER_DEGREE * cos / COS_SCALE / E7;
+    let y = dlat * METRES_PER_DEGREE / E7;
+    (x, y)
+}
+
+fn distance_sq_m(point: &GeoPoint, lat_e7: i32, lon_e7: i32) -> i128 {
+    let (x, y) = project_m(point, lat_e7, lon_e7);
+    x * x + y * y
+}
+
+// Squared distance from `point` (the projection origin) to segment a-b.
+fn segment_distance_sq_m(point: &GeoPoint, a: &Waypoint, b: &Waypoint) -> i128 {
+    let (ax, ay) = project_m(point, a.lat_e7, a.lon_e7);
+    let (bx, by) = project_m(point, b.lat_e7, b.lon_e7);
+    let (abx, aby) = (bx - ax, by - ay);
+    let len_sq = abx * abx + aby * aby;
+
+    // Vector from a to the origin is (-ax, -ay).
+    let dot = -ax * abx - ay * aby;
+    if len_sq == 0 || dot <= 0 {
+        return ax * ax + ay * ay;
+    }
+    if dot >= len_sq {
+        return bx * bx + by * by;
+    }
+    let cross = ax * aby - ay * abx;
+    cross * cross / len_sq
+}
+
+// Bhaskara I's approximation of cos for |x| <= 90 degrees, x in 1e-7 degrees,
+// scaled by COS_SCALE. Error stays under 0.2%.
+fn cos_deg_scaled(x_e7: i128) -> i128 {
+    let x = x_e7.abs().min(90 * E7) / 100_000; // hundredths of a degree
+    let x_sq = x * x;
+    let full = 32_400 * 10_000;
+    COS_SCALE * (full - 4 * x_sq) / (full + x_sq)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(lat_e7: i32, lon_e7: i32) -> GeoPoint {
+        GeoPoint { lat_e7, lon_e7, accuracy_m: 0, timestamp: 1 }
+    }
+
+    fn route(waypoints: &[(i32, i32)], corridor_width_m: u32) -> RouteCorridor {
+        RouteCorridor {
+            batch: Pubkey::default(),
+            waypoints: waypoints.iter().map(|&(lat_e7, lon_e7)| Waypoint { lat_e7, lon_e7 }).collect(),
+            corridor_width_m,
+            destination: None,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn approximates_cos_within_tolerance() {
+        assert_eq!(cos_deg_scaled(0), COS_SCALE);
+        assert_eq!(cos_deg_scaled(60 * E7), COS_SCALE / 2);
+        assert_eq!(cos_deg_scaled(-60 * E7), COS_SCALE / 2);
+        assert_eq!(cos_deg_scaled(90 * E7), 0);
+        assert_eq!(cos_deg_scaled(100 * E7), 0);
+        // cos 45° = 0.707107
+        assert!((cos_deg_scaled(45 * E7) - 707_107).abs() < 1_415);
+    }
+
+    #[test]
+    fn measures_distance_to_a_segment() {
+        let a = Waypoint { lat_e7: 0, lon_e7: -100_000 };
+        let b = Waypoint { lat_e7: 0, lon_e7: 100_000 };
+        assert_eq!(segment_distance_sq_m(&point(0, 0), &a, &b), 0);
+        assert_eq!(segment_distance_sq_m(&point(0, 50_000), &a, &b), 0);
+
+        // 0.001° north of the segment is about 111 m.
+        let beside = segment_distance_sq_m(&point(10_000, 0), &a, &b);
+        assert!((110 * 110..=112 * 112).contains(&beside));
+
+        // Past an end the distance is to that endpoint (0.001° east, ~111 m).
+        let beyond = segment_distance_sq_m(&point(0, 110_000), &a, &b);
+        assert!((110 * 110..=112 * 112).contains(&beyond));
+    }
+
+    #[test]
+    fn accepts_points_on_the_route() {
+        let corridor = route(&[(0, 0), (0, E7 as i32)], 1_000);
+        assert!(within_route(&corridor, &point(0, 0)));
+        assert!(within_route(&corridor, &point(0, 5_000_000)));
+        assert!(within_route(&corridor, &point(0, E7 as i32)));
+    }
+
+    #[test]
+    fn holds_the_corridor_edge() {
+        let corridor = route(&[(0, 0), (0, E7 as i32)], 1_000);
+        // 0.0089° is about 990 m, 0.0091° about 1 012 m.
+        assert!(within_route(&corridor, &point(89_000, 5_000_000)));
+        assert!(within_route(&corridor, &point(-89_000, 5_000_000)));
+        assert!(!within_route(&corridor, &point(91_000, 5_000_000)));
+        assert!(!within_route(&corridor, &point(-91_000, 5_000_000)));
+
+        // The fix's accuracy radius counts in its favour.
+        let fuzzy = GeoPoint { accuracy_m: 20, ..point(91_000, 5_000_000) };
+        assert!(within_route(&corridor, &fuzzy));
+    }
+
+    #[test]
+    fn shrinks_longitude_at_high_latitude() {
+        // One degree of longitude at 60°N is about 55.6 km, half the
+        // equatorial length.
+        let wide = route(&[(60 * E7 as i32, 10 * E7 as i32), (60 * E7 as i32, 11 * E7 as i32)], 56_000);
+        let narrow = route(&[(60 * E7 as i32, 10 * E7 as i32), (60 * E7 as i32, 11 * E7 as i32)], 55_000);
+        let east = point(60 * E7 as i32, 12 * E7 as i32);
+        assert!(within_route(&wide, &east));
+        assert!(!within_route(&narrow, &east));
+    }
+
+    #[test]
+    fn wraps_across_the_antimeridian() {
+        let corridor = route(&[(0, 1_799_000_000), (0, -1_799_000_000)], 1_000);
+        assert!(within_route(&corridor, &point(0, 1_800_000_000)));
+        assert!(within_route(&corridor, &point(0, -1_800_000_000)));
+        assert!(within_route(&corridor, &point(50_000, -1_799_500_000)));
+        // 0.02° off the crossing is about 2.2 km.
+        assert!(!within_route(&corridor, &point(200_000, 1_800_000_000)));
+    }
+
+    #[test]
+    fn treats_a_single_waypoint_as_a_circle() {
+        let corridor = route(&[(520_000_000, 40_000_000)], 500);
+        // 0.004° of latitude is about 445 m, 0.005° about 556 m.
+        assert!(within_route(&corridor, &point(520_040_000, 40_000_000)));
+        assert!(!within_route(&corridor, &point(520_050_000, 40_000_000)));
+        assert!(!within_route(&route(&[], 500), &point(520_000_000, 40_000_000)));
+    }
+}
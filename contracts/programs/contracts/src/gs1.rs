@@ -0,0 +1,81 @@
+// GS1 identifier validation. Numeric keys (GTIN, SSCC) end in a mod-10 check
+// digit; lot numbers are AI (10) values drawn from GS1 character set 82.
+
+use crate::state::{GTIN_LENGTH, LOT_LENGTH, SSCC_LENGTH};
+
+/// Check digit for the leading digits of a GS1 key: weights alternate 3, 1
+/// starting from the rightmost digit.
+/// Callers must have checked that every byte is an ASCII digit.
+fn check_digit(digits: &[u8]) -> u8 {
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| (d - b'0') as u32 * if i % 2 == 0 { 3 } else { 1 })
+        .sum();
+    b'0' + ((10 - sum % 10) % 10) as u8
+}
+
+fn valid_key(key: &str, lengths: &[usize]) -> bool {
+    let bytes = key.as_bytes();
+    lengths.contains(&bytes.len())
+        && bytes.iter().all(u8::is_ascii_digit)
+        && check_digit(&bytes[..bytes.len() - 1]) == bytes[bytes.len() - 1]
+}
+
+/// Accepts GTIN-8, -12, -13 and -14 and returns the GTIN-14 form (left
+/// padded with zeros) used in lookup seeds, so every length of the same
+/// trade item resolves to one account.
+pub fn normalize_gtin(gtin: &str) -> Option<String> {
+    valid_key(gtin, &[8, 12, 13, 14]).then(|| format!("{:0>width$}", gtin, width = GTIN_LENGTH))
+}
+
+pub fn valid_sscc(sscc: &str) -> bool {
+    valid_key(sscc, &[SSCC_LENGTH])
+}
+
+pub fn valid_lot(lot: &str) -> bool {
+    !lot.is_empty()
+        && lot.len() <= LOT_LENGTH
+        && lot.bytes().all(|b| {
+            b.is_ascii_alphanumeric() || b"!\"%&'()*+,-./:;<=>?_".contains(&b)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Examples from the GS1 General Specifications and check digit calculator.
+    #[test]
+    fn normalizes_every_gtin_length_to_gtin_14() {
+        assert_eq!(normalize_gtin("96385074").as_deref(), Some("00000096385074"));
+        assert_eq!(normalize_gtin("036000291452").as_deref(), Some("00036000291452"));
+        assert_eq!(normalize_gtin("4006381333931").as_deref(), Some("04006381333931"));
+        assert_eq!(normalize_gtin("10614141000415").as_deref(), Some("10614141000415"));
+    }
+
+    #[test]
+    fn rejects_malformed_gtins() {
+        assert_eq!(normalize_gtin("4006381333932"), None);
+        assert_eq!(normalize_gtin("400638133393"), None);
+        assert_eq!(normalize_gtin("40063813339A1"), None);
+        assert_eq!(normalize_gtin(""), None);
+    }
+
+    #[test]
+    fn checks_the_sscc_check_digit() {
+        assert!(valid_sscc("106141412345678908"));
+        assert!(!valid_sscc("106141412345678909"));
+        assert!(!valid_sscc("10614141234567890"));
+        assert!(!valid_sscc("10614141234567890/"));
+    }
+
+    #[test]
+    fn accepts_lots_from_character_set_82() {
+        assert!(valid_lot("ABC-123/4"));
+        assert!(!valid_lot(""));
+        assert!(!valid_lot("LOT 1"));
+        assert!(!valid_lot(&"A".repeat(LOT_LENGTH + 1)));
+    }
+}
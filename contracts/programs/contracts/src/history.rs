@@ -0,0 +1,183 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hashv;
+
+use crate::{errors::SupplyChainError, state::{Batch, Event, PolicyConfig, CLOSING_EVENT_SLOTS, EVENT_LENGTH}};
+
+const LEAF_PREFIX: &[u8] = &[0];
+const NODE_PREFIX: &[u8] = &[1];
+
+/// Merkle root over a batch's event log, in log order. Leaves and inner nodes
+/// are domain-separated so a leaf can never be passed off as a subtree; an odd
+/// node at the end of a level is carried up unchanged. An empty log hashes to
+/// all zeros.
+pub fn events_merkle_root(events: &[Event]) -> Result<[u8; 32]> {
+    if events.is_empty() {
+        return Ok([0u8; 32]);
+    }
+
+    let mut level = events
+        .iter()
+        .map(|event| Ok(hashv(&[LEAF_PREFIX, &event.try_to_vec()?]).to_bytes()))
+        .collect::<Result<Vec<[u8; 32]>>>()?;
+
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => hashv(&[NODE_PREFIX, left, right]).to_bytes(),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+
+    Ok(level[0])
+}
+
+/// Appends an event to the batch log and folds it into `Batch.history_root`.
+/// Every instruction that records an event must go through here (or
+/// `append_closing_event`) so the chain stays in step with the log. The log is
+/// capped at `policy.max_events`, which never reaches into the
+/// `CLOSING_EVENT_SLOTS` at the end of the account.
+pub fn append_event(batch: &mut Batch, policy: &PolicyConfig, event: Event) -> Result<()> {
+    require!(
+        batch.events.len() < (policy.max_events as usize).min(EVENT_LENGTH - CLOSING_EVENT_SLOTS),
+        SupplyChainError::TooManyEvents
+    );
+    push_event(batch, event)
+}
+
+/// Appends the event that closes a batch. It ignores `policy.max_events`,
+/// which an admin may have lowered below the length of existing logs, and may
+/// use the held-back closing slots, so a full log never keeps a batch from
+/// reaching Closed.
+pub fn append_closing_event(batch: &mut Batch, event: Event) -> Result<()> {
+    require!(
+        batch.events.len() < EVENT_LENGTH,
+        SupplyChainError::TooManyEvents
+    );
+    push_event(batch, event)
+}
+
+fn push_event(batch: &mut Batch, event: Event) -> Result<()> {
+    batch.history_root = next_history_root(&batch.history_root, &event)?;
+    batch.events.push(event);
+    Ok(())
+}
+
+/// One link of the history chain: `sha256(previous_root || borsh(event))`.
+pub fn next_history_root(previous_root: &[u8; 32], event: &Event) -> Result<[u8; 32]> {
+    Ok(hashv(&[previous_root, &event.try_to_vec()?]).to_bytes())
+}
+
+/// Recomputes the history chain from the genesis root (all zeros) over
+/// `events` in order.
+pub fn compute_history_root(events: &[Event]) -> Result<[u8; 32]> {
+    events
+        .iter()
+        .try_fold([0u8; 32], |root, event| next_history_root(&root, event))
+}
+
+/// Off-chain check that a copy of a batch's event log is complete and in
+/// order: any dropped, reordered or altered event changes the recomputed root.
+pub fn verify_history(events: &[Event], expected_root: &[u8; 32]) -> Result<bool> {
+    Ok(compute_history_root(events)? == *expected_root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{policy::default_policy, state::EventType};
+
+    fn event(event_type: EventType, timestamp: i64) -> Event {
+        Event {
+            event_type,
+            timestamp,
+            from_wallet: Pubkey::new_from_array([1; 32]),
+            to_wallet: Pubkey::new_from_array([2; 32]),
+            details_hash: [timestamp as u8; 32],
+            details_cid: String::new(),
+            location: None,
+        }
+    }
+
+    // An all-zero account image decodes to a batch with an empty log.
+    fn empty_batch() -> Batch {
+        let zeroed = vec![0u8; Batch::INIT_SPACE];
+        Batch::deserialize(&mut zeroed.as_slice()).unwrap()
+    }
+
+    fn log() -> Vec<Event> {
+        vec![
+            event(EventType::HandOver, 100),
+            event(EventType::Checkpoint, 200),
+            event(EventType::StorageUpdate, 300),
+        ]
+    }
+
+    #[test]
+    fn verifies_a_complete_chain() {
+        let events = log();
+        let root = events
+            .iter()
+            .try_fold([0u8; 32], |root, event| next_history_root(&root, event))
+            .unwrap();
+
+        assert_ne!(root, [0u8; 32]);
+        assert_eq!(compute_history_root(&events).unwrap(), root);
+        assert!(verify_history(&events, &root).unwrap());
+        assert_eq!(compute_history_root(&[]).unwrap(), [0u8; 32]);
+    }
+
+    #[test]
+    fn rejects_a_dropped_event() {
+        let events = log();
+        let root = compute_history_root(&events).unwrap();
+
+        let mut dropped = events.clone();
+        dropped.remove(1);
+        assert!(!verify_history(&dropped, &root).unwrap());
+        assert!(!verify_history(&events[..2], &root).unwrap());
+    }
+
+    #[test]
+    fn rejects_a_reordered_event() {
+        let events = log();
+        let root = compute_history_root(&events).unwrap();
+
+        let mut reordered = events.clone();
+        reordered.swap(0, 1);
+        assert!(!verify_history(&reordered, &root).unwrap());
+    }
+
+    #[test]
+    fn holds_back_a_slot_for_the_closing_event() {
+        let policy = default_policy();
+        let mut batch = empty_batch();
+        for i in 0..policy.max_events as i64 {
+            append_event(&mut batch, &policy, event(EventType::Checkpoint, i)).unwrap();
+        }
+        assert_eq!(batch.events.len(), EVENT_LENGTH - CLOSING_EVENT_SLOTS);
+        assert!(append_event(&mut batch, &policy, event(EventType::Checkpoint, 99)).is_err());
+
+        append_closing_event(&mut batch, event(EventType::Disposal, 100)).unwrap();
+        assert_eq!(batch.events.len(), EVENT_LENGTH);
+        assert!(verify_history(&batch.events, &batch.history_root).unwrap());
+        assert!(append_closing_event(&mut batch, event(EventType::Disposal, 101)).is_err());
+    }
+
+    #[test]
+    fn closes_a_log_longer_than_a_lowered_cap() {
+        let mut policy = default_policy();
+        let mut batch = empty_batch();
+        for event in log() {
+            append_event(&mut batch, &policy, event).unwrap();
+        }
+
+        policy.max_events = 2;
+        assert!(append_event(&mut batch, &policy, event(EventType::Sale, 400)).is_err());
+        append_closing_event(&mut batch, event(EventType::Sale, 400)).unwrap();
+        assert_eq!(batch.events.len(), 4);
+        assert!(verify_history(&batch.events, &batch.history_root).unwrap());
+    }
+}
@@ -1,6 +1,6 @@
 use anchor_lang::prelude::*;
 
-use crate::{errors::SupplyChainError, events::BatchCreated, state::{Batch, BatchStatus, Event, EventType, OriginDetails, Role, SystemConfig, UserProfile, BATCH_ID_LENGTH, EVENT_LENGTH, METADATA_CID_LENGTH}};
+use crate::{errors::SupplyChainError, events::BatchCreated, history::append_event, instructions::{_mark_flagged, _open_leg, _set_route}, policy::{allowed_cid, is_severe_reason, reason_matches}, state::{Batch, BatchIdEntry, BatchIdIndex, BatchStatus, Event, EventType, IoTHistory, LegSummary, OriginDetails, Product, Role, RouteCorridor, RoutePlan, SystemConfig, UserProfile, BATCH_ID_LENGTH, MAX_BATCH_ID_ENTRIES, METADATA_CID_LENGTH}};
 
 #[derive(Accounts)]
 #[instruction(batch_id: String)]
@@ -9,20 +9,78 @@ pub struct CreateBatch<'info> {
         init,
         payer = user,
         space = 8 + Batch::INIT_SPACE,
-        seeds = [b"batch", batch_id.as_bytes()],
+        seeds = [b"batch", user.key().as_ref(), batch_id.as_bytes()],
         bump
     )]
     pub batch: Account<'info, Batch>,
+
+    #[account(
+        init,
+        payer = user,
+        space = 8 + IoTHistory::INIT_SPACE,
+        seeds = [b"iot_history", batch.key().as_ref()],
+        bump
+    )]
+    pub iot_history: Account<'info, IoTHistory>,
+
+    #[account(
+        init,
+        payer = user,
+        space = 8 + LegSummary::INIT_SPACE,
+        seeds = [b"leg", batch.key().as_ref(), &0u16.to_le_bytes()],
+        bump
+    )]
+    pub first_leg: Account<'info, LegSummary>,
+
+    // Supplied together with a `route` argument; batches created without one
+    // can still get a corridor from `define_route` before they leave.
+    #[account(
+        init,
+        payer = user,
+        space = 8 + RouteCorridor::INIT_SPACE,
+        seeds = [b"route", batch.key().as_ref()],
+        bump
+    )]
+    pub route: Option<Account<'info, RouteCorridor>>,
+
+    #[account(
+        seeds = [b"product", product.sku.as_bytes()],
+        bump = product.bump
+    )]
+    pub product: Account<'info, Product>,
     
+    /// CHECK: only inspected for emptiness so the ID of an archived batch cannot be reused
     #[account(
-        seeds = [b"user", user.key().as_ref()],
+        seeds = [b"archive", user.key().as_ref(), batch_id.as_bytes()],
+        bump
+    )]
+    pub archive: UncheckedAccount<'info>,
+
+    // Shared by every producer using this id; the first one pays for it.
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + BatchIdIndex::INIT_SPACE,
+        seeds = [b"batch_id", batch_id.as_bytes()],
         bump
     )]
+    pub batch_index: Account<'info, BatchIdIndex>,
+
+    #[account(
+        seeds = [b"user", user.key().as_ref()],
+        bump = user_profile.bump
+    )]
     pub user_profile: Account<'info, UserProfile>,
     
     #[account(mut)]
     pub user: Signer<'info>,
     
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config: Account<'info, SystemConfig>,
+
     pub system_program: Program<'info, System>,
 }
 
@@ -32,6 +90,8 @@ pub fn _create_batch(
         origin_details: OriginDetails,
         metadata_hash: [u8; 32],
         metadata_cid: String,
+        metadata_schema_version: u16,
+        route: Option<RoutePlan>,
     ) -> Result<()> {
         let batch = &mut ctx.accounts.batch;
         let user_profile = &ctx.accounts.user_profile;
@@ -49,31 +109,111 @@ pub fn _create_batch(
             user_profile.user_wallet == user.key(),
             SupplyChainError::WalletMismatch
         );
+        require!(
+            origin_details.product == ctx.accounts.product.key(),
+            SupplyChainError::ProductMismatch
+        );
         require!(
             origin_details.production_date > 0,
             SupplyChainError::InvalidProductionDate
         );
         require!(
-            !batch_id.is_empty() && batch_id.len() <= BATCH_ID_LENGTH,
-            SupplyChainError::InvalidBatchId
+            origin_details.expiry_date > origin_details.production_date
+                && origin_details.expiry_date > Clock::get()?.unix_timestamp,
+            SupplyChainError::InvalidExpiryDate
         );
         require!(
-            !metadata_cid.is_empty() && metadata_cid.len() <= METADATA_CID_LENGTH,
-            SupplyChainError::InvalidMetadataCid
+            origin_details.weight_grams > 0,
+            SupplyChainError::InvalidWeight
         );
+        require!(
+            !batch_id.is_empty() && batch_id.len() <= BATCH_ID_LENGTH,
+            SupplyChainError::InvalidBatchId
+        );
+        let metadata_cid = allowed_cid(&ctx.accounts.system_config.policy, &metadata_cid, METADATA_CID_LENGTH).ok_or(SupplyChainError::InvalidMetadataCid)?;
         require!(
             metadata_hash != [0u8; 32],
             SupplyChainError::InvalidMetadataHash
         );
+        require!(
+            metadata_schema_version > 0,
+            SupplyChainError::InvalidMetadataSchemaVersion
+        );
+        require!(
+            ctx.accounts.archive.data_is_empty(),
+            SupplyChainError::BatchIdArchived
+        );
 
         batch.id = batch_id;
+        batch.bump = ctx.bumps.batch;
         batch.producer = user.key();
         batch.current_owner = user.key();
+        batch.current_custodian = user.key();
         batch.status = BatchStatus::Registered;
+        batch.pre_flag_status = BatchStatus::Registered;
         batch.origin_details = origin_details;
         batch.metadata_hash = metadata_hash;
         batch.metadata_cid = metadata_cid;
+        batch.metadata_schema_version = metadata_schema_version;
         batch.events = Vec::new();
+        batch.history_root = [0u8; 32];
+        batch.threshold = ctx.accounts.product.default_threshold.clone();
+
+        let iot_history = &mut ctx.accounts.iot_history;
+        iot_history.batch = batch.key();
+        iot_history.head = 0;
+        iot_history.entries = Vec::new();
+        iot_history.bump = ctx.bumps.iot_history;
+
+        batch.current_leg = 0;
+        batch.handover_count = 0;
+        _open_leg(
+            &mut ctx.accounts.first_leg,
+            batch.key(),
+            0,
+            user.key(),
+            user.key(),
+            Clock::get()?.unix_timestamp,
+            ctx.bumps.first_leg,
+        );
+        batch.remaining_quantity = batch.origin_details.quantity;
+        batch.reserved_quantity = 0;
+        batch.closure_reason = None;
+        batch.route_defined = false;
+        batch.shipment_open = false;
+        batch.shipment_outcome = None;
+        let now = Clock::get()?.unix_timestamp;
+        let product = &ctx.accounts.product;
+        batch.remaining_shelf_life_seconds = if product.shelf_life_seconds > 0 {
+            product.shelf_life_seconds
+        } else {
+            batch.origin_details.expiry_date.saturating_sub(now)
+        };
+        batch.degradation = product.degradation.clone();
+        batch.gs1_lot = String::new();
+        batch.shelf_life_updated_at = now;
+        batch.effective_expiry = batch
+            .origin_details
+            .expiry_date
+            .min(now.saturating_add(batch.remaining_shelf_life_seconds));
+
+        let batch_index = &mut ctx.accounts.batch_index;
+        require!(
+            batch_index.entries.len() < MAX_BATCH_ID_ENTRIES,
+            SupplyChainError::BatchIdIndexFull
+        );
+        batch_index.id = batch.id.clone();
+        batch_index.entries.push(BatchIdEntry {
+            producer: batch.producer,
+            batch: batch.key(),
+        });
+        batch_index.bump = ctx.bumps.batch_index;
+
+        match (ctx.accounts.route.as_mut(), route, ctx.bumps.route) {
+            (Some(account), Some(plan), Some(bump)) => _set_route(account, batch, plan, bump)?,
+            (None, None, _) => {}
+            _ => return err!(SupplyChainError::InvalidRoute),
+        }
 
         emit!(BatchCreated {
             batch_id: batch.id.clone(),
@@ -85,10 +225,13 @@ pub fn _create_batch(
 }
 
 
-
 #[derive(Accounts)]
 pub struct FlagBatch<'info> {
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
     pub batch: Account<'info, Batch>,
     
     #[account(
@@ -137,30 +280,34 @@ pub fn _flag_batch(
         batch.status != BatchStatus::Recalled,
         SupplyChainError::BatchAlreadyRecalled
     );
+    require!(
+        batch.status != BatchStatus::Closed,
+        SupplyChainError::BatchAlreadyClosed
+    );
+    require!(
+        batch.status != BatchStatus::Expired,
+        SupplyChainError::BatchExpired
+    );
 
     require!(
         !reason.is_empty(),
         SupplyChainError::EmptyReason
     );
 
-    let is_severe = reason.to_lowercase().contains("severe") || 
-                    reason.to_lowercase().contains("critical") ||
-                    reason.to_lowercase().contains("recall");
-    
-    if is_severe {
+    if is_severe_reason(&system_config.policy, &reason) {
         batch.status = BatchStatus::Recalled;
     } else {
-        batch.status = BatchStatus::Flagged;
+        _mark_flagged(batch);
     }
 
-    if reason.to_lowercase().contains("temperature") || reason.to_lowercase().contains("cold") {
+    if reason_matches(&system_config.policy.cold_chain_keywords, &reason) {
         batch.compliance.cold_chain_compliant = false;
     }
-    if reason.to_lowercase().contains("fraud") {
+    if reason_matches(&system_config.policy.fraud_keywords, &reason) {
         batch.compliance.fraud_detected = true;
     }
 
-    if reason.to_lowercase().contains("breach") || reason.to_lowercase().contains("temperature") {
+    if reason_matches(&system_config.policy.breach_keywords, &reason) {
         batch.iot_summary.breach_count = batch.iot_summary.breach_count.checked_add(1).unwrap_or(batch.iot_summary.breach_count);
     }
 
@@ -184,13 +331,10 @@ pub fn _flag_batch(
         to_wallet: caller.key(),
         details_hash,
         details_cid: String::new(), 
+        location: None,
     };
 
-    require!(
-        batch.events.len() < EVENT_LENGTH,
-        SupplyChainError::TooManyEvents
-    );
-    batch.events.push(flag_event);
+    append_event(batch, &ctx.accounts.system_config.policy, flag_event)?;
 
     Ok(())
 }
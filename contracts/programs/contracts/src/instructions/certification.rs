@@ -1,6 +1,6 @@
 use anchor_lang::prelude::*;
 
-use crate::{errors::SupplyChainError, state::{Batch, Certification, Event, EventType, Role, UserProfile, CERTIFICATION_CID_LENGTH, CERTIFICATION_TYPE_LENGTH, EVENT_LENGTH}};
+use crate::{errors::SupplyChainError, events::CertificationRevoked, history::append_event, policy::allowed_cid, state::{Batch, Certification, Event, EventType, Role, SystemConfig, UserProfile, CERTIFICATION_CID_LENGTH, CERTIFICATION_TYPE_LENGTH}};
 #[derive(Accounts)]
 #[instruction(cert_type: String)]
 pub struct IssueCertification<'info> {
@@ -8,12 +8,16 @@ pub struct IssueCertification<'info> {
         init,
         payer = issuer,
         space = 8 + Certification::INIT_SPACE,
-        seeds = [b"cert", batch.id.as_bytes(), cert_type.as_bytes()],
+        seeds = [b"cert", batch.key().as_ref(), cert_type.as_bytes()],
         bump
     )]
     pub certification: Account<'info, Certification>,
     
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
     pub batch: Account<'info, Batch>,
     
     #[account(
@@ -25,10 +29,62 @@ pub struct IssueCertification<'info> {
     #[account(mut)]
     pub issuer: Signer<'info>,
     
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config: Account<'info, SystemConfig>,
+
     pub system_program: Program<'info, System>,
 }
 
 
+#[derive(Accounts)]
+pub struct RevokeCertification<'info> {
+    #[account(
+        mut,
+        seeds = [b"cert", batch.key().as_ref(), certification.cert_type.as_bytes()],
+        bump = certification.bump
+    )]
+    pub certification: Account<'info, Certification>,
+
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    pub revoker: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config: Account<'info, SystemConfig>,
+}
+
+// Migration for batches whose `certification_issued` flag was set by the old
+// compliance check: recounts the batch's valid Certification accounts, passed
+// as remaining accounts, and stores the count and the flag derived from it.
+#[derive(Accounts)]
+pub struct SyncCertificationStatus<'info> {
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config: Account<'info, SystemConfig>,
+}
+
 pub fn _issue_certification(
     ctx: Context<IssueCertification>,
     cert_type: String,
@@ -63,10 +119,7 @@ pub fn _issue_certification(
         cert_hash != [0u8; 32],
         SupplyChainError::InvalidDetailsHash
     );
-    require!(
-        !cert_cid.is_empty() && cert_cid.len() <= CERTIFICATION_CID_LENGTH,
-        SupplyChainError::InvalidDetailsCid
-    );
+    let cert_cid = allowed_cid(&ctx.accounts.system_config.policy, &cert_cid, CERTIFICATION_CID_LENGTH).ok_or(SupplyChainError::InvalidDetailsCid)?;
     require!(
         !cert_type.is_empty() && cert_type.len() <= CERTIFICATION_TYPE_LENGTH,
         SupplyChainError::InvalidCertificationType
@@ -81,6 +134,7 @@ pub fn _issue_certification(
     certification.valid = true;
     certification.bump = ctx.bumps.certification;
 
+    batch.certification_count = batch.certification_count.saturating_add(1);
     batch.compliance.certification_issued = true;
 
     let cert_event = Event {
@@ -90,13 +144,103 @@ pub fn _issue_certification(
         to_wallet: issuer.key(),
         details_hash: cert_hash,
         details_cid: cert_cid,
+        location: None,
+    };
+
+    append_event(batch, &ctx.accounts.system_config.policy, cert_event)?;
+
+    Ok(())
+}
+
+pub fn _revoke_certification(
+    ctx: Context<RevokeCertification>,
+    reason_hash: [u8; 32],
+) -> Result<()> {
+    let certification = &mut ctx.accounts.certification;
+    let batch = &mut ctx.accounts.batch;
+    let revoker = &ctx.accounts.revoker;
+    let system_config = &ctx.accounts.system_config;
+    let clock = Clock::get()?;
+
+    require!(
+        revoker.key() == certification.issuer || revoker.key() == system_config.admin_wallet,
+        SupplyChainError::InvalidRole
+    );
+    require!(
+        certification.valid,
+        SupplyChainError::CertificationAlreadyRevoked
+    );
+    require!(
+        reason_hash != [0u8; 32],
+        SupplyChainError::InvalidDetailsHash
+    );
+
+    certification.valid = false;
+    batch.certification_count = batch.certification_count.saturating_sub(1);
+    batch.compliance.certification_issued = batch.certification_count > 0;
+
+    let revoke_event = Event {
+        event_type: EventType::CertificationRevoked,
+        timestamp: clock.unix_timestamp,
+        from_wallet: revoker.key(),
+        to_wallet: certification.issuer,
+        details_hash: reason_hash,
+        details_cid: String::new(),
+        location: None,
     };
 
+    append_event(batch, &system_config.policy, revoke_event)?;
+
+    emit!(CertificationRevoked {
+        batch_id: batch.id.clone(),
+        certification: certification.key(),
+        cert_type: certification.cert_type.clone(),
+        revoked_by: revoker.key(),
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+pub fn _sync_certification_status<'info>(ctx: Context<'_, '_, 'info, 'info, SyncCertificationStatus<'info>>) -> Result<()> {
+    let batch = &mut ctx.accounts.batch;
+
     require!(
-        batch.events.len() < EVENT_LENGTH,
-        SupplyChainError::TooManyEvents
+        ctx.accounts.admin.key() == ctx.accounts.system_config.admin_wallet,
+        SupplyChainError::InvalidRole
     );
-    batch.events.push(cert_event);
+
+    let batch_key = batch.key();
+    let mut seen: Vec<Pubkey> = Vec::new();
+    let mut valid_count: u16 = 0;
+    for info in ctx.remaining_accounts.iter() {
+        require!(
+            info.owner == &crate::ID && !seen.contains(info.key),
+            SupplyChainError::InvalidCertificationAccount
+        );
+        let data = info.try_borrow_data()?;
+        require!(
+            data.get(..8) == Some(Certification::DISCRIMINATOR),
+            SupplyChainError::InvalidCertificationAccount
+        );
+        let certification = Certification::try_deserialize(&mut &data[..])?;
+        let address = Pubkey::create_program_address(
+            &[b"cert", batch_key.as_ref(), certification.cert_type.as_bytes(), &[certification.bump]],
+            &crate::ID,
+        );
+        require!(
+            address.ok() == Some(info.key()) && certification.batch_id == batch.id,
+            SupplyChainError::InvalidCertificationAccount
+        );
+        seen.push(info.key());
+
+        if certification.valid {
+            valid_count = valid_count.saturating_add(1);
+        }
+    }
+
+    batch.certification_count = valid_count;
+    batch.compliance.certification_issued = valid_count > 0;
 
     Ok(())
 }
@@ -0,0 +1,337 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hashv;
+
+use crate::{errors::SupplyChainError, events::{ConsumerClaimCancelled, ConsumerClaimIssued, ConsumerClaimRedeemed}, history::append_event, instructions::{_close_batch, _require_not_expired}, state::{Batch, BatchStatus, ClaimCommitment, ClosureReason, ConsumerClaim, Event, EventType, Role, SystemConfig, UserProfile}};
+
+#[derive(Accounts)]
+#[instruction(code_hash: [u8; 32])]
+pub struct IssueConsumerClaim<'info> {
+    #[account(
+        init,
+        payer = retailer,
+        space = 8 + ConsumerClaim::INIT_SPACE,
+        seeds = [b"claim", batch.key().as_ref(), code_hash.as_ref()],
+        bump
+    )]
+    pub claim: Account<'info, ConsumerClaim>,
+
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(
+        seeds = [b"user", retailer.key().as_ref()],
+        bump = retailer_profile.bump
+    )]
+    pub retailer_profile: Account<'info, UserProfile>,
+
+    #[account(mut)]
+    pub retailer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CommitConsumerClaim<'info> {
+    #[account(
+        init,
+        payer = consumer,
+        space = 8 + ClaimCommitment::INIT_SPACE,
+        seeds = [b"claim_commit", claim.key().as_ref(), consumer.key().as_ref()],
+        bump
+    )]
+    pub commitment: Account<'info, ClaimCommitment>,
+
+    #[account(
+        seeds = [b"claim", claim.batch.as_ref(), claim.code_hash.as_ref()],
+        bump = claim.bump
+    )]
+    pub claim: Account<'info, ConsumerClaim>,
+
+    #[account(mut)]
+    pub consumer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RedeemConsumerClaim<'info> {
+    #[account(
+        mut,
+        seeds = [b"claim", batch.key().as_ref(), claim.code_hash.as_ref()],
+        bump = claim.bump,
+        has_one = batch
+    )]
+    pub claim: Account<'info, ConsumerClaim>,
+
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(
+        mut,
+        close = consumer,
+        seeds = [b"claim_commit", claim.key().as_ref(), consumer.key().as_ref()],
+        bump = commitment.bump,
+        has_one = claim,
+        has_one = consumer
+    )]
+    pub commitment: Account<'info, ClaimCommitment>,
+
+    // Any wallet may redeem; end customers are not expected to register.
+    #[account(mut)]
+    pub consumer: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config: Account<'info, SystemConfig>,
+}
+
+#[derive(Accounts)]
+pub struct CancelConsumerClaim<'info> {
+    #[account(
+        mut,
+        close = retailer,
+        seeds = [b"claim", batch.key().as_ref(), claim.code_hash.as_ref()],
+        bump = claim.bump,
+        has_one = batch,
+        has_one = retailer
+    )]
+    pub claim: Account<'info, ConsumerClaim>,
+
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(mut)]
+    pub retailer: Signer<'info>,
+}
+
+pub fn _issue_consumer_claim(
+    ctx: Context<IssueConsumerClaim>,
+    code_hash: [u8; 32],
+    quantity: u64,
+) -> Result<()> {
+    let claim = &mut ctx.accounts.claim;
+    let batch = &mut ctx.accounts.batch;
+    let retailer_profile = &ctx.accounts.retailer_profile;
+    let retailer = &ctx.accounts.retailer;
+    let clock = Clock::get()?;
+
+    require!(
+        retailer_profile.role == Role::Retailer,
+        SupplyChainError::InvalidRole
+    );
+    require!(
+        retailer_profile.is_approved,
+        SupplyChainError::UserNotApproved
+    );
+    require!(
+        retailer.key() == batch.current_owner,
+        SupplyChainError::NotCurrentOwner
+    );
+    require!(
+        batch.status == BatchStatus::Sold,
+        SupplyChainError::BatchNotForSale
+    );
+    _require_not_expired(batch, clock.unix_timestamp)?;
+    require!(
+        quantity > 0 && quantity <= batch.remaining_quantity.saturating_sub(batch.reserved_quantity),
+        SupplyChainError::InvalidQuantity
+    );
+    require!(
+        code_hash != [0u8; 32],
+        SupplyChainError::InvalidDetailsHash
+    );
+
+    batch.reserved_quantity += quantity;
+
+    claim.batch = batch.key();
+    claim.retailer = retailer.key();
+    claim.code_hash = code_hash;
+    claim.quantity = quantity;
+    claim.issued_at = clock.unix_timestamp;
+    claim.redeemed_by = None;
+    claim.redeemed_at = 0;
+    claim.bump = ctx.bumps.claim;
+
+    emit!(ConsumerClaimIssued {
+        batch_id: batch.id.clone(),
+        claim: claim.key(),
+        retailer: retailer.key(),
+        quantity,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+pub fn _commit_consumer_claim(
+    ctx: Context<CommitConsumerClaim>,
+    commitment_hash: [u8; 32],
+) -> Result<()> {
+    let commitment = &mut ctx.accounts.commitment;
+    let claim = &ctx.accounts.claim;
+
+    require!(
+        claim.redeemed_by.is_none(),
+        SupplyChainError::ClaimAlreadyRedeemed
+    );
+    require!(
+        commitment_hash != [0u8; 32],
+        SupplyChainError::InvalidDetailsHash
+    );
+
+    commitment.claim = claim.key();
+    commitment.consumer = ctx.accounts.consumer.key();
+    commitment.commitment = commitment_hash;
+    commitment.committed_slot = Clock::get()?.slot;
+    commitment.bump = ctx.bumps.commitment;
+
+    Ok(())
+}
+
+// The claim code is the sha256 preimage of `code_hash`, revealed only after
+// the consumer committed to it from an earlier slot. Redeeming takes the
+// reserved units out of the retailer's stock; once the batch is sold out the
+// last redeeming consumer becomes its final owner.
+pub fn _redeem_consumer_claim(
+    ctx: Context<RedeemConsumerClaim>,
+    code: [u8; 32],
+) -> Result<()> {
+    let claim = &mut ctx.accounts.claim;
+    let batch = &mut ctx.accounts.batch;
+    let consumer = &ctx.accounts.consumer;
+    let clock = Clock::get()?;
+
+    require!(
+        claim.redeemed_by.is_none(),
+        SupplyChainError::ClaimAlreadyRedeemed
+    );
+    require!(
+        hashv(&[&code]).to_bytes() == claim.code_hash,
+        SupplyChainError::InvalidClaimCode
+    );
+    require!(
+        hashv(&[&code, consumer.key().as_ref()]).to_bytes() == ctx.accounts.commitment.commitment,
+        SupplyChainError::InvalidClaimCode
+    );
+    require!(
+        ctx.accounts.commitment.committed_slot < clock.slot,
+        SupplyChainError::ClaimCommitmentTooRecent
+    );
+    require!(
+        batch.status == BatchStatus::Sold && batch.current_owner == claim.retailer,
+        SupplyChainError::BatchNotForSale
+    );
+    _require_not_expired(batch, clock.unix_timestamp)?;
+    require!(
+        claim.quantity <= batch.reserved_quantity && claim.quantity <= batch.remaining_quantity,
+        SupplyChainError::InvalidQuantity
+    );
+
+    batch.reserved_quantity -= claim.quantity;
+    batch.remaining_quantity -= claim.quantity;
+    claim.redeemed_by = Some(consumer.key());
+    claim.redeemed_at = clock.unix_timestamp;
+
+    let handover_event = Event {
+        event_type: EventType::HandOver,
+        timestamp: clock.unix_timestamp,
+        from_wallet: claim.retailer,
+        to_wallet: consumer.key(),
+        details_hash: claim.code_hash,
+        details_cid: String::new(),
+        location: None,
+    };
+
+    append_event(batch, &ctx.accounts.system_config.policy, handover_event)?;
+
+    emit!(ConsumerClaimRedeemed {
+        batch_id: batch.id.clone(),
+        claim: claim.key(),
+        consumer: consumer.key(),
+        quantity: claim.quantity,
+        remaining_quantity: batch.remaining_quantity,
+        timestamp: clock.unix_timestamp,
+    });
+
+    if batch.remaining_quantity == 0 {
+        batch.current_owner = consumer.key();
+        batch.current_custodian = consumer.key();
+        _close_batch(batch, &consumer.key(), ClosureReason::SoldOut, clock.unix_timestamp);
+    }
+
+    Ok(())
+}
+
+// Withdraws a claim that was never redeemed, e.g. a lost code or a returned
+// item, and hands its units back to the retailer's stock. Commitments made
+// against the claim are passed as remaining accounts together with the
+// consumer wallets that paid for them, and are closed back to those wallets.
+pub fn _cancel_consumer_claim<'info>(ctx: Context<'_, '_, 'info, 'info, CancelConsumerClaim<'info>>) -> Result<()> {
+    let claim = &ctx.accounts.claim;
+    let batch = &mut ctx.accounts.batch;
+    let clock = Clock::get()?;
+
+    require!(
+        claim.redeemed_by.is_none(),
+        SupplyChainError::ClaimAlreadyRedeemed
+    );
+
+    batch.reserved_quantity = batch.reserved_quantity.saturating_sub(claim.quantity);
+
+    let claim_key = claim.key();
+    let accounts = ctx.remaining_accounts;
+    for info in accounts.iter() {
+        if info.owner != &crate::ID {
+            continue;
+        }
+        let commitment = {
+            let data = info.try_borrow_data()?;
+            require!(
+                data.get(..8) == Some(ClaimCommitment::DISCRIMINATOR),
+                SupplyChainError::InvalidClaimCommitment
+            );
+            ClaimCommitment::try_deserialize(&mut &data[..])?
+        };
+        let address = Pubkey::create_program_address(
+            &[b"claim_commit", claim_key.as_ref(), commitment.consumer.as_ref(), &[commitment.bump]],
+            &crate::ID,
+        );
+        require!(
+            commitment.claim == claim_key && address.ok() == Some(info.key()),
+            SupplyChainError::InvalidClaimCommitment
+        );
+
+        let recipient = accounts
+            .iter()
+            .find(|account| account.key() == commitment.consumer)
+            .ok_or(SupplyChainError::MissingRentRecipient)?;
+        **recipient.try_borrow_mut_lamports()? += info.lamports();
+        **info.try_borrow_mut_lamports()? = 0;
+        info.assign(&System::id());
+        info.resize(0)?;
+    }
+
+    emit!(ConsumerClaimCancelled {
+        batch_id: batch.id.clone(),
+        claim: claim_key,
+        retailer: claim.retailer,
+        quantity: claim.quantity,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
@@ -0,0 +1,422 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::SupplyChainError, events::{BatchArchived, BatchClosed, SaleRecorded}, history::{append_closing_event, append_event, events_merkle_root}, instructions::_require_not_expired, policy::allowed_cid, state::{Batch, BatchArchive, BatchIdIndex, BatchStatus, ClosureReason, Dispute, DisputeStatus, Event, EventType, IoTHistory, LegSummary, Role, RouteCorridor, ServiceBinding, ShipmentPlan, SystemConfig, UserProfile, DETAILS_CID_LENGTH}};
+
+#[derive(Accounts)]
+pub struct RecordSale<'info> {
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(
+        seeds = [b"user", seller.key().as_ref()],
+        bump = seller_profile.bump
+    )]
+    pub seller_profile: Account<'info, UserProfile>,
+
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config: Account<'info, SystemConfig>,
+}
+
+#[derive(Accounts)]
+pub struct MarkConsumed<'info> {
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(
+        seeds = [b"user", owner.key().as_ref()],
+        bump = owner_profile.bump
+    )]
+    pub owner_profile: Account<'info, UserProfile>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config: Account<'info, SystemConfig>,
+}
+
+#[derive(Accounts)]
+pub struct MarkDestroyed<'info> {
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(
+        seeds = [b"user", caller.key().as_ref()],
+        bump = caller_profile.bump
+    )]
+    pub caller_profile: Account<'info, UserProfile>,
+
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config: Account<'info, SystemConfig>,
+}
+
+// The batch's legs, route corridor, shipment plan, service bindings and ruled
+// disputes are passed as remaining accounts and closed along with it; any
+// wallet that paid for one of them, other than the producer, is passed too so
+// its rent can be refunded.
+#[derive(Accounts)]
+pub struct ArchiveBatch<'info> {
+    #[account(
+        init,
+        payer = producer,
+        space = 8 + BatchArchive::INIT_SPACE,
+        seeds = [b"archive", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump
+    )]
+    pub archive: Account<'info, BatchArchive>,
+
+    #[account(
+        mut,
+        close = producer,
+        has_one = producer,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(
+        mut,
+        close = producer,
+        seeds = [b"iot_history", batch.key().as_ref()],
+        bump = iot_history.bump
+    )]
+    pub iot_history: Account<'info, IoTHistory>,
+
+    #[account(
+        mut,
+        seeds = [b"batch_id", batch.id.as_bytes()],
+        bump = batch_index.bump
+    )]
+    pub batch_index: Account<'info, BatchIdIndex>,
+
+    #[account(mut)]
+    pub producer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn _record_sale(
+    ctx: Context<RecordSale>,
+    quantity: u64,
+    sale_hash: [u8; 32],
+) -> Result<()> {
+    let batch = &mut ctx.accounts.batch;
+    let seller_profile = &ctx.accounts.seller_profile;
+    let seller = &ctx.accounts.seller;
+    let clock = Clock::get()?;
+
+    require!(
+        seller_profile.role == Role::Retailer,
+        SupplyChainError::InvalidRole
+    );
+    require!(
+        seller_profile.is_approved,
+        SupplyChainError::UserNotApproved
+    );
+    require!(
+        seller.key() == batch.current_owner,
+        SupplyChainError::NotCurrentOwner
+    );
+    require!(
+        batch.status == BatchStatus::Sold,
+        SupplyChainError::BatchNotForSale
+    );
+    _require_not_expired(batch, clock.unix_timestamp)?;
+    require!(
+        quantity > 0 && quantity <= batch.remaining_quantity.saturating_sub(batch.reserved_quantity),
+        SupplyChainError::InvalidQuantity
+    );
+    require!(
+        sale_hash != [0u8; 32],
+        SupplyChainError::InvalidDetailsHash
+    );
+
+    batch.remaining_quantity -= quantity;
+
+    let sale_event = Event {
+        event_type: EventType::Sale,
+        timestamp: clock.unix_timestamp,
+        from_wallet: seller.key(),
+        to_wallet: seller.key(),
+        details_hash: sale_hash,
+        details_cid: String::new(),
+        location: None,
+    };
+
+    if batch.remaining_quantity == 0 {
+        append_closing_event(batch, sale_event)?;
+    } else {
+        append_event(batch, &ctx.accounts.system_config.policy, sale_event)?;
+    }
+
+    emit!(SaleRecorded {
+        batch_id: batch.id.clone(),
+        seller: seller.key(),
+        quantity,
+        remaining_quantity: batch.remaining_quantity,
+        timestamp: clock.unix_timestamp,
+    });
+
+    if batch.remaining_quantity == 0 {
+        _close_batch(batch, &seller.key(), ClosureReason::SoldOut, clock.unix_timestamp);
+    }
+
+    Ok(())
+}
+
+pub fn _mark_consumed(
+    ctx: Context<MarkConsumed>,
+    details_hash: [u8; 32],
+) -> Result<()> {
+    let batch = &mut ctx.accounts.batch;
+    let owner_profile = &ctx.accounts.owner_profile;
+    let owner = &ctx.accounts.owner;
+    let clock = Clock::get()?;
+
+    require!(
+        matches!(owner_profile.role, Role::Retailer | Role::Consumer),
+        SupplyChainError::InvalidRole
+    );
+    require!(
+        owner_profile.is_approved,
+        SupplyChainError::UserNotApproved
+    );
+    require!(
+        owner.key() == batch.current_owner,
+        SupplyChainError::NotCurrentOwner
+    );
+    require!(
+        batch.status == BatchStatus::Sold,
+        SupplyChainError::BatchNotForSale
+    );
+    _require_not_expired(batch, clock.unix_timestamp)?;
+    require!(
+        details_hash != [0u8; 32],
+        SupplyChainError::InvalidDetailsHash
+    );
+
+    let consumption_event = Event {
+        event_type: EventType::Consumption,
+        timestamp: clock.unix_timestamp,
+        from_wallet: owner.key(),
+        to_wallet: owner.key(),
+        details_hash,
+        details_cid: String::new(),
+        location: None,
+    };
+
+    append_closing_event(batch, consumption_event)?;
+
+    batch.remaining_quantity = 0;
+    batch.reserved_quantity = 0;
+    _close_batch(batch, &owner.key(), ClosureReason::Consumed, clock.unix_timestamp);
+
+    Ok(())
+}
+
+pub fn _mark_destroyed(
+    ctx: Context<MarkDestroyed>,
+    disposal_hash: [u8; 32],
+    disposal_cid: String,
+) -> Result<()> {
+    let batch = &mut ctx.accounts.batch;
+    let caller_profile = &ctx.accounts.caller_profile;
+    let caller = &ctx.accounts.caller;
+    let clock = Clock::get()?;
+
+    require!(
+        caller_profile.is_approved,
+        SupplyChainError::UserNotApproved
+    );
+    require!(
+        caller.key() == batch.current_owner || caller_profile.role == Role::Regulator,
+        SupplyChainError::NotCurrentOwner
+    );
+    require!(
+        batch.status != BatchStatus::Closed,
+        SupplyChainError::BatchAlreadyClosed
+    );
+    require!(
+        disposal_hash != [0u8; 32],
+        SupplyChainError::InvalidDetailsHash
+    );
+    let disposal_cid = allowed_cid(&ctx.accounts.system_config.policy, &disposal_cid, DETAILS_CID_LENGTH).ok_or(SupplyChainError::InvalidDetailsCid)?;
+
+    let disposal_event = Event {
+        event_type: EventType::Disposal,
+        timestamp: clock.unix_timestamp,
+        from_wallet: caller.key(),
+        to_wallet: batch.current_owner,
+        details_hash: disposal_hash,
+        details_cid: disposal_cid,
+        location: None,
+    };
+
+    append_closing_event(batch, disposal_event)?;
+
+    batch.remaining_quantity = 0;
+    batch.reserved_quantity = 0;
+    _close_batch(batch, &caller.key(), ClosureReason::Destroyed, clock.unix_timestamp);
+
+    Ok(())
+}
+
+pub fn _archive_batch<'info>(ctx: Context<'_, '_, 'info, 'info, ArchiveBatch<'info>>) -> Result<()> {
+    let archive = &mut ctx.accounts.archive;
+    let batch = &ctx.accounts.batch;
+    let clock = Clock::get()?;
+
+    // Only closed batches are archived. A recalled batch is not: it has to be
+    // disposed of through `mark_destroyed` first, which closes it.
+    require!(
+        batch.status == BatchStatus::Closed,
+        SupplyChainError::BatchNotTerminal
+    );
+
+    _close_batch_accounts(batch.key(), &ctx.accounts.producer.to_account_info(), ctx.remaining_accounts)?;
+
+    // Whoever archives the id's last batch gets the index's rent.
+    let batch_index = &mut ctx.accounts.batch_index;
+    batch_index.entries.retain(|entry| entry.batch != batch.key());
+    if batch_index.entries.is_empty() {
+        batch_index.close(ctx.accounts.producer.to_account_info())?;
+    }
+
+    archive.id = batch.id.clone();
+    archive.producer = batch.producer;
+    archive.final_status = batch.status.clone();
+    archive.closure_reason = batch.closure_reason.clone();
+    archive.event_count = batch.events.len() as u32;
+    archive.events_root = events_merkle_root(&batch.events)?;
+    archive.history_root = batch.history_root;
+    archive.compliance = batch.compliance.clone();
+    archive.archived_at = clock.unix_timestamp;
+    archive.bump = ctx.bumps.archive;
+
+    emit!(BatchArchived {
+        batch_id: archive.id.clone(),
+        archive: archive.key(),
+        producer: archive.producer,
+        events_root: archive.events_root,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+// Closes every program-owned account in `accounts`. Each must be one of the
+// batch's satellite accounts at the address its own seeds derive to, and its
+// rent goes back to whoever paid for it.
+fn _close_batch_accounts<'info>(
+    batch: Pubkey,
+    producer: &AccountInfo<'info>,
+    accounts: &[AccountInfo<'info>],
+) -> Result<()> {
+    for info in accounts.iter().filter(|info| info.owner == &crate::ID) {
+        let (owner_batch, address, payer) = {
+            let data = info.try_borrow_data()?;
+            let discriminator = data.get(..8).ok_or(SupplyChainError::InvalidArchiveAccount)?;
+            let mut data: &[u8] = &data;
+            if discriminator == LegSummary::DISCRIMINATOR {
+                let leg = LegSummary::try_deserialize(&mut data)?;
+                let address = Pubkey::create_program_address(
+                    &[b"leg", leg.batch.as_ref(), &leg.leg_index.to_le_bytes(), &[leg.bump]],
+                    &crate::ID,
+                );
+                (leg.batch, address, leg.payer)
+            } else if discriminator == RouteCorridor::DISCRIMINATOR {
+                let route = RouteCorridor::try_deserialize(&mut data)?;
+                let address = Pubkey::create_program_address(
+                    &[b"route", route.batch.as_ref(), &[route.bump]],
+                    &crate::ID,
+                );
+                (route.batch, address, producer.key())
+            } else if discriminator == ShipmentPlan::DISCRIMINATOR {
+                let plan = ShipmentPlan::try_deserialize(&mut data)?;
+                let address = Pubkey::create_program_address(
+                    &[b"shipment", plan.batch.as_ref(), &[plan.bump]],
+                    &crate::ID,
+                );
+                (plan.batch, address, plan.sender)
+            } else if discriminator == ServiceBinding::DISCRIMINATOR {
+                let binding = ServiceBinding::try_deserialize(&mut data)?;
+                let address = Pubkey::create_program_address(
+                    &[b"service", binding.batch.as_ref(), binding.custodian.as_ref(), binding.provider.as_ref(), &[binding.bump]],
+                    &crate::ID,
+                );
+                (binding.batch, address, binding.custodian)
+            } else if discriminator == Dispute::DISCRIMINATOR {
+                let dispute = Dispute::try_deserialize(&mut data)?;
+                require!(
+                    dispute.status != DisputeStatus::Open,
+                    SupplyChainError::DisputeStillOpen
+                );
+                let address = Pubkey::create_program_address(
+                    &[b"dispute", dispute.batch.as_ref(), dispute.iot_hash.as_ref(), &[dispute.bump]],
+                    &crate::ID,
+                );
+                (dispute.batch, address, dispute.opened_by)
+            } else {
+                return err!(SupplyChainError::InvalidArchiveAccount);
+            }
+        };
+        require!(
+            owner_batch == batch && address.ok() == Some(info.key()),
+            SupplyChainError::InvalidArchiveAccount
+        );
+
+        let recipient = if payer == producer.key() {
+            producer
+        } else {
+            accounts
+                .iter()
+                .find(|account| account.key() == payer)
+                .ok_or(SupplyChainError::MissingRentRecipient)?
+        };
+        **recipient.try_borrow_mut_lamports()? += info.lamports();
+        **info.try_borrow_mut_lamports()? = 0;
+        info.assign(&System::id());
+        info.resize(0)?;
+    }
+
+    Ok(())
+}
+
+pub fn _close_batch(batch: &mut Batch, closed_by: &Pubkey, reason: ClosureReason, timestamp: i64) {
+    batch.status = BatchStatus::Closed;
+    batch.closure_reason = Some(reason.clone());
+
+    emit!(BatchClosed {
+        batch_id: batch.id.clone(),
+        closed_by: *closed_by,
+        reason,
+        timestamp,
+    });
+}
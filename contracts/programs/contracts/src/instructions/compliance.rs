@@ -1,21 +1,52 @@
 use anchor_lang::prelude::*;
 
-use crate::{errors::SupplyChainError, state::{Batch, BatchStatus, Event, EventType, Role, SystemConfig, UserProfile, EVENT_LENGTH}};
+use crate::{errors::SupplyChainError, history::append_event, instructions::_mark_flagged, state::{Batch, BatchStatus, ComplianceReport, Event, EventType, IoTHistory, PolicyConfig, Role, SystemConfig, UserProfile}};
+
+#[derive(Accounts)]
+pub struct EvaluateCompliance<'info> {
+    #[account(
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(
+        seeds = [b"iot_history", batch.key().as_ref()],
+        bump = iot_history.bump
+    )]
+    pub iot_history: Account<'info, IoTHistory>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config: Account<'info, SystemConfig>,
+}
 
 #[derive(Accounts)]
 pub struct CheckCompliance<'info> {
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
     pub batch: Account<'info, Batch>,
-    
+
+    #[account(
+        seeds = [b"iot_history", batch.key().as_ref()],
+        bump = iot_history.bump
+    )]
+    pub iot_history: Account<'info, IoTHistory>,
+
     #[account(
         seeds = [b"user", caller.key().as_ref()],
         bump = caller_profile.bump
     )]
     pub caller_profile: Account<'info, UserProfile>,
-    
+
     #[account(mut)]
     pub caller: Signer<'info>,
-    
+
     #[account(
         seeds = [b"config"],
         bump = system_config.bump
@@ -23,6 +54,19 @@ pub struct CheckCompliance<'info> {
     pub system_config: Account<'info, SystemConfig>,
 }
 
+/// Read-only evaluation meant to be simulated; the report comes back as the
+/// transaction's return data and nothing on the batch changes.
+pub fn _evaluate_compliance(ctx: Context<EvaluateCompliance>) -> Result<ComplianceReport> {
+    let clock = Clock::get()?;
+
+    Ok(_build_compliance_report(
+        &ctx.accounts.batch,
+        &ctx.accounts.iot_history,
+        ctx.accounts.system_config.policy.iot_staleness_seconds,
+        clock.unix_timestamp,
+    ))
+}
+
 pub fn _check_compliance(ctx: Context<CheckCompliance>) -> Result<()> {
     let batch = &mut ctx.accounts.batch;
     let caller_profile = &ctx.accounts.caller_profile;
@@ -34,64 +78,103 @@ pub fn _check_compliance(ctx: Context<CheckCompliance>) -> Result<()> {
         caller_profile.role == Role::Regulator || caller.key() == system_config.oracle_wallet,
         SupplyChainError::InvalidRole
     );
-    
+
     if caller_profile.role == Role::Regulator {
         require!(
             caller_profile.is_approved,
             SupplyChainError::UserNotApproved
         );
     }
-    
+
     require!(
         caller.key() == caller_profile.user_wallet || caller.key() == system_config.oracle_wallet,
         SupplyChainError::WalletMismatch
     );
+    require!(
+        batch.status != BatchStatus::Closed,
+        SupplyChainError::BatchAlreadyClosed
+    );
+    require!(
+        batch.status != BatchStatus::Recalled,
+        SupplyChainError::BatchAlreadyRecalled
+    );
+    require!(
+        batch.status != BatchStatus::Expired,
+        SupplyChainError::BatchExpired
+    );
+
+    let report = _build_compliance_report(
+        batch,
+        &ctx.accounts.iot_history,
+        system_config.policy.iot_staleness_seconds,
+        clock.unix_timestamp,
+    );
 
     require!(
-        clock.unix_timestamp - batch.iot_summary.timestamp <= 3600,
+        report.iot_data_fresh,
         SupplyChainError::StaleIoTData
     );
 
-    _internal_check_compliance(batch, &caller.key(), clock.unix_timestamp)?;
+    _apply_compliance_report(batch, &system_config.policy, &report, &caller.key(), clock.unix_timestamp)?;
 
     Ok(())
 }
 
+pub fn _build_compliance_report(
+    batch: &Batch,
+    iot_history: &IoTHistory,
+    staleness_seconds: i64,
+    now: i64,
+) -> ComplianceReport {
+    let temperature_ok = batch.iot_summary.max_temp_centi_c <= batch.threshold.max_temp_centi_c;
+    let humidity_ok = batch.iot_summary.max_humidity_bps <= batch.threshold.max_humidity_bps;
+    // A breach overturned by an upheld dispute no longer counts against the batch.
+    let breach_reported = batch.iot_summary.breach_detected
+        && !iot_history.entries.iter().any(|e| e.iot_hash == batch.iot_hash && e.breach_overturned);
+    // Readings older than the staleness window can't vouch for the batch's
+    // current condition, so they never yield a compliant verdict.
+    let iot_data_fresh = now.saturating_sub(batch.iot_summary.timestamp) <= staleness_seconds;
+    let route_ok = !batch.compliance.route_deviation;
 
-pub fn _internal_check_compliance(batch: &mut Batch, caller_wallet: &Pubkey, timestamp: i64) -> Result<()> {
-    let mut compliance_failed = false;
-
-    if batch.iot_summary.max_temp > batch.threshold.max_temp {
-        batch.compliance.cold_chain_compliant = false;
-        compliance_failed = true;
+    ComplianceReport {
+        evaluated_at: now,
+        iot_timestamp: batch.iot_summary.timestamp,
+        iot_data_fresh,
+        temperature_ok,
+        humidity_ok,
+        breach_reported,
+        max_temp_centi_c: batch.iot_summary.max_temp_centi_c,
+        threshold_max_temp_centi_c: batch.threshold.max_temp_centi_c,
+        max_humidity_bps: batch.iot_summary.max_humidity_bps,
+        threshold_max_humidity_bps: batch.threshold.max_humidity_bps,
+        recent_summaries: iot_history.entries.len() as u8,
+        recent_breaches: iot_history.entries.iter().filter(|e| e.breach_detected && !e.breach_overturned).count() as u8,
+        route_ok,
+        compliant: iot_data_fresh && temperature_ok && humidity_ok && !breach_reported && route_ok,
     }
+}
 
-    if batch.iot_summary.max_humidity > batch.threshold.max_humidity {
+pub fn _apply_compliance_report(batch: &mut Batch, policy: &PolicyConfig, report: &ComplianceReport, caller_wallet: &Pubkey, timestamp: i64) -> Result<()> {
+    if !report.compliant {
         batch.compliance.cold_chain_compliant = false;
-        compliance_failed = true;
-    }
+        _mark_flagged(batch);
 
-    if compliance_failed {
-        batch.status = BatchStatus::Flagged;
-        
         let compliance_event = Event {
             event_type: EventType::ComplianceCheck,
             timestamp,
             from_wallet: *caller_wallet,
             to_wallet: *caller_wallet,
             details_hash: [0u8; 32],
-            details_cid: String::new(), 
+            details_cid: String::new(),
+            location: None,
         };
 
-        require!(
-            batch.events.len() < EVENT_LENGTH,
-            SupplyChainError::TooManyEvents
-        );
-        batch.events.push(compliance_event);
+        append_event(batch, policy, compliance_event)?;
     } else {
-        batch.compliance.certification_issued = true;
-        batch.status = BatchStatus::Compliant;
+        // The verdict is about the cold chain only; where the batch is in its
+        // lifecycle is left to the handover and closure instructions.
+        batch.compliance.cold_chain_compliant = true;
     }
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -1,7 +1,8 @@
 use anchor_lang::prelude::*;
-use crate::state::SystemConfig;
+use crate::state::{PolicyConfig, SystemConfig};
 use crate::errors::CustomError;
-use crate::events::InitializeConfigEvent;
+use crate::events::{InitializeConfigEvent, PolicyUpdated};
+use crate::policy::{default_policy, validate_policy};
 #[derive(Accounts)]
 pub struct InitializeConfig<'info>{
     #[account(init,payer=payer,space=8+SystemConfig::INIT_SPACE,seeds=[b"config"],bump)]
@@ -33,17 +34,51 @@ pub fn _initialze_config(
     config.admin_wallet = admin_wallet;
     config.oracle_wallet = oracle_wallet;
     config.bump = ctx.bumps.system_config;
+    config.policy = default_policy();
 
     emit!(InitializeConfigEvent{
         config:config.key(),
-        admin_wallet:admin_wallet,
-        oracle_wallet:oracle_wallet
+        admin_wallet,
+        oracle_wallet
     });
     Ok(())
 }
 
+#[derive(Accounts)]
+pub struct UpdateConfig<'info>{
+    pub admin:Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config:Account<'info,SystemConfig>
+}
 
+// `policy.version` must match the stored version so two admins editing from
+// the same snapshot cannot silently overwrite each other.
+pub fn _update_policy(
+    ctx:Context<UpdateConfig>,
+    policy:PolicyConfig
+)->Result<()>{
+    let config=&mut ctx.accounts.system_config;
 
+    require!(
+        ctx.accounts.admin.key() == config.admin_wallet,
+        CustomError::Unauthorized
+    );
+    require!(
+        policy.version == config.policy.version,
+        CustomError::PolicyVersionMismatch
+    );
+    validate_policy(&policy)?;
 
+    let version = config.policy.version.checked_add(1).ok_or(CustomError::InvalidPolicy)?;
+    config.policy = PolicyConfig { version, ..policy };
 
-
+    emit!(PolicyUpdated{
+        config:config.key(),
+        version
+    });
+    Ok(())
+}
@@ -0,0 +1,245 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::SupplyChainError, events::{CustodyUpdateLogged, ServiceProviderBound}, geo::{validate_geo_point, waypoint_of}, history::append_event, instructions::_check_route, policy::allowed_cid, state::{Batch, BatchStatus, Event, EventType, GeoPoint, Role, RouteCorridor, ServiceBinding, SystemConfig, UserProfile, DETAILS_CID_LENGTH}};
+
+#[derive(Accounts)]
+pub struct BindServiceProvider<'info> {
+    #[account(
+        init,
+        payer = custodian,
+        space = 8 + ServiceBinding::INIT_SPACE,
+        seeds = [b"service", batch.key().as_ref(), custodian.key().as_ref(), provider_profile.user_wallet.as_ref()],
+        bump
+    )]
+    pub service_binding: Account<'info, ServiceBinding>,
+
+    #[account(
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(
+        seeds = [b"user", provider_profile.user_wallet.as_ref()],
+        bump = provider_profile.bump
+    )]
+    pub provider_profile: Account<'info, UserProfile>,
+
+    #[account(mut)]
+    pub custodian: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnbindServiceProvider<'info> {
+    #[account(
+        mut,
+        close = custodian,
+        seeds = [b"service", batch.key().as_ref(), custodian.key().as_ref(), service_binding.provider.as_ref()],
+        bump = service_binding.bump,
+        has_one = batch,
+        has_one = custodian @ SupplyChainError::NotCurrentCustodian
+    )]
+    pub service_binding: Account<'info, ServiceBinding>,
+
+    #[account(
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(mut)]
+    pub custodian: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct LogCustodyUpdate<'info> {
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(
+        seeds = [b"user", caller.key().as_ref()],
+        bump = caller_profile.bump
+    )]
+    pub caller_profile: Account<'info, UserProfile>,
+
+    pub caller: Signer<'info>,
+
+    // Only needed when the caller is not the custodian themselves. Bindings
+    // are keyed by the custodian that made them, so one left behind by an
+    // earlier custodian never resolves here.
+    #[account(
+        seeds = [b"service", batch.key().as_ref(), batch.current_custodian.as_ref(), caller.key().as_ref()],
+        bump = service_binding.bump
+    )]
+    pub service_binding: Option<Account<'info, ServiceBinding>>,
+
+    #[account(
+        seeds = [b"route", batch.key().as_ref()],
+        bump = route.bump
+    )]
+    pub route: Option<Account<'info, RouteCorridor>>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config: Account<'info, SystemConfig>,
+}
+
+pub fn _bind_service_provider(ctx: Context<BindServiceProvider>) -> Result<()> {
+    let binding = &mut ctx.accounts.service_binding;
+    let batch = &ctx.accounts.batch;
+    let provider_profile = &ctx.accounts.provider_profile;
+    let custodian = &ctx.accounts.custodian;
+    let clock = Clock::get()?;
+
+    require!(
+        custodian.key() == batch.current_custodian,
+        SupplyChainError::NotCurrentCustodian
+    );
+    require!(
+        batch.status != BatchStatus::Closed,
+        SupplyChainError::BatchAlreadyClosed
+    );
+    require!(
+        provider_profile.is_approved
+            && matches!(provider_profile.role, Role::Transporter | Role::Warehouse | Role::Processor),
+        SupplyChainError::InvalidServiceProvider
+    );
+
+    binding.batch = batch.key();
+    binding.custodian = custodian.key();
+    binding.provider = provider_profile.user_wallet;
+    binding.bound_at = clock.unix_timestamp;
+    binding.bump = ctx.bumps.service_binding;
+
+    emit!(ServiceProviderBound {
+        batch_id: batch.id.clone(),
+        custodian: custodian.key(),
+        provider: binding.provider,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+pub fn _unbind_service_provider(_ctx: Context<UnbindServiceProvider>) -> Result<()> {
+    Ok(())
+}
+
+pub fn _log_checkpoint(
+    ctx: Context<LogCustodyUpdate>,
+    location: GeoPoint,
+    details_hash: [u8; 32],
+    details_cid: String,
+) -> Result<()> {
+    validate_geo_point(&location)?;
+    _log_custody_update(ctx, EventType::Checkpoint, Some(location), details_hash, details_cid)
+}
+
+pub fn _log_storage_update(
+    ctx: Context<LogCustodyUpdate>,
+    details_hash: [u8; 32],
+    details_cid: String,
+) -> Result<()> {
+    require!(
+        ctx.accounts.caller_profile.role == Role::Warehouse,
+        SupplyChainError::InvalidRole
+    );
+    _log_custody_update(ctx, EventType::StorageUpdate, None, details_hash, details_cid)
+}
+
+pub fn _log_processing_update(
+    ctx: Context<LogCustodyUpdate>,
+    details_hash: [u8; 32],
+    details_cid: String,
+) -> Result<()> {
+    require!(
+        ctx.accounts.caller_profile.role == Role::Processor,
+        SupplyChainError::InvalidRole
+    );
+    _log_custody_update(ctx, EventType::ProcessingUpdate, None, details_hash, details_cid)
+}
+
+// Appends a typed event on behalf of the custodian without moving ownership.
+fn _log_custody_update(
+    ctx: Context<LogCustodyUpdate>,
+    event_type: EventType,
+    location: Option<GeoPoint>,
+    details_hash: [u8; 32],
+    details_cid: String,
+) -> Result<()> {
+    let batch = &mut ctx.accounts.batch;
+    let caller_profile = &ctx.accounts.caller_profile;
+    let caller = &ctx.accounts.caller;
+    let clock = Clock::get()?;
+
+    require!(
+        caller_profile.is_approved,
+        SupplyChainError::UserNotApproved
+    );
+    require!(
+        caller.key() == caller_profile.user_wallet,
+        SupplyChainError::WalletMismatch
+    );
+
+    let bound = ctx
+        .accounts
+        .service_binding
+        .as_ref()
+        .is_some_and(|binding| binding.custodian == batch.current_custodian);
+    require!(
+        caller.key() == batch.current_custodian || bound,
+        SupplyChainError::NotCustodianOrProvider
+    );
+
+    require!(
+        !matches!(batch.status, BatchStatus::Recalled | BatchStatus::Closed),
+        SupplyChainError::BatchNotCompliant
+    );
+    require!(
+        details_hash != [0u8; 32],
+        SupplyChainError::InvalidDetailsHash
+    );
+    let details_cid = allowed_cid(&ctx.accounts.system_config.policy, &details_cid, DETAILS_CID_LENGTH).ok_or(SupplyChainError::InvalidDetailsCid)?;
+
+    let event = Event {
+        event_type: event_type.clone(),
+        timestamp: clock.unix_timestamp,
+        from_wallet: caller.key(),
+        to_wallet: batch.current_custodian,
+        details_hash,
+        details_cid,
+        location: location.as_ref().map(waypoint_of),
+    };
+
+    append_event(batch, &ctx.accounts.system_config.policy, event)?;
+
+    emit!(CustodyUpdateLogged {
+        batch_id: batch.id.clone(),
+        event_type,
+        actor: caller.key(),
+        custodian: batch.current_custodian,
+        location: location.clone(),
+        timestamp: clock.unix_timestamp,
+    });
+
+    if let Some(location) = &location {
+        _check_route(
+            batch,
+            ctx.accounts.route.as_deref(),
+            &ctx.accounts.system_config.policy,
+            location,
+            caller.key(),
+            clock.unix_timestamp,
+        )?;
+    }
+
+    Ok(())
+}
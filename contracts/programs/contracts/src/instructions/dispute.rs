@@ -0,0 +1,246 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::SupplyChainError, events::{DisputeOpened, DisputeRuled}, history::append_event, policy::allowed_cid, state::{Batch, Dispute, DisputeStatus, Event, EventType, IoTHistory, Role, SystemConfig, UserProfile, DETAILS_CID_LENGTH}};
+
+#[derive(Accounts)]
+#[instruction(iot_hash: [u8; 32])]
+pub struct OpenDispute<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + Dispute::INIT_SPACE,
+        seeds = [b"dispute", batch.key().as_ref(), iot_hash.as_ref()],
+        bump
+    )]
+    pub dispute: Account<'info, Dispute>,
+
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(
+        seeds = [b"iot_history", batch.key().as_ref()],
+        bump = iot_history.bump
+    )]
+    pub iot_history: Account<'info, IoTHistory>,
+
+    #[account(
+        seeds = [b"user", owner.key().as_ref()],
+        bump = owner_profile.bump
+    )]
+    pub owner_profile: Account<'info, UserProfile>,
+
+    #[account(
+        seeds = [b"user", regulator_profile.user_wallet.as_ref()],
+        bump = regulator_profile.bump
+    )]
+    pub regulator_profile: Account<'info, UserProfile>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config: Account<'info, SystemConfig>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RuleDispute<'info> {
+    #[account(
+        mut,
+        seeds = [b"dispute", batch.key().as_ref(), dispute.iot_hash.as_ref()],
+        bump = dispute.bump,
+        has_one = batch,
+        has_one = regulator @ SupplyChainError::NotAssignedRegulator
+    )]
+    pub dispute: Account<'info, Dispute>,
+
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(
+        mut,
+        seeds = [b"iot_history", batch.key().as_ref()],
+        bump = iot_history.bump
+    )]
+    pub iot_history: Account<'info, IoTHistory>,
+
+    #[account(
+        seeds = [b"user", regulator.key().as_ref()],
+        bump = regulator_profile.bump
+    )]
+    pub regulator_profile: Account<'info, UserProfile>,
+
+    #[account(mut)]
+    pub regulator: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config: Account<'info, SystemConfig>,
+}
+
+pub fn _open_dispute(
+    ctx: Context<OpenDispute>,
+    iot_hash: [u8; 32],
+    evidence_hash: [u8; 32],
+    evidence_cid: String,
+) -> Result<()> {
+    let dispute = &mut ctx.accounts.dispute;
+    let batch = &mut ctx.accounts.batch;
+    let owner_profile = &ctx.accounts.owner_profile;
+    let regulator_profile = &ctx.accounts.regulator_profile;
+    let owner = &ctx.accounts.owner;
+    let clock = Clock::get()?;
+
+    require!(
+        owner_profile.is_approved,
+        SupplyChainError::UserNotApproved
+    );
+    require!(
+        owner.key() == batch.current_owner,
+        SupplyChainError::NotCurrentOwner
+    );
+    require!(
+        regulator_profile.role == Role::Regulator,
+        SupplyChainError::InvalidRole
+    );
+    require!(
+        regulator_profile.is_approved,
+        SupplyChainError::UserNotApproved
+    );
+    // Any summary still held in the history ring buffer can be disputed, not
+    // just the latest one, as long as it reported a breach.
+    let entry = ctx
+        .accounts
+        .iot_history
+        .entries
+        .iter()
+        .find(|entry| entry.iot_hash == iot_hash)
+        .ok_or(SupplyChainError::SummaryNotInHistory)?;
+    require!(
+        entry.breach_detected,
+        SupplyChainError::NoBreachToDispute
+    );
+    let summary_timestamp = entry.timestamp;
+    require!(
+        evidence_hash != [0u8; 32],
+        SupplyChainError::InvalidDetailsHash
+    );
+    let evidence_cid = allowed_cid(&ctx.accounts.system_config.policy, &evidence_cid, DETAILS_CID_LENGTH).ok_or(SupplyChainError::InvalidDetailsCid)?;
+
+    dispute.batch = batch.key();
+    dispute.opened_by = owner.key();
+    dispute.regulator = regulator_profile.user_wallet;
+    dispute.iot_hash = iot_hash;
+    dispute.summary_timestamp = summary_timestamp;
+    dispute.evidence_hash = evidence_hash;
+    dispute.evidence_cid = evidence_cid.clone();
+    dispute.status = DisputeStatus::Open;
+    dispute.ruling_hash = [0u8; 32];
+    dispute.opened_at = clock.unix_timestamp;
+    dispute.resolved_at = 0;
+    dispute.bump = ctx.bumps.dispute;
+
+    let dispute_event = Event {
+        event_type: EventType::DisputeOpened,
+        timestamp: clock.unix_timestamp,
+        from_wallet: owner.key(),
+        to_wallet: dispute.regulator,
+        details_hash: evidence_hash,
+        details_cid: evidence_cid,
+        location: None,
+    };
+
+    append_event(batch, &ctx.accounts.system_config.policy, dispute_event)?;
+
+    emit!(DisputeOpened {
+        batch_id: batch.id.clone(),
+        dispute: dispute.key(),
+        opened_by: owner.key(),
+        regulator: dispute.regulator,
+        iot_hash: dispute.iot_hash,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+pub fn _rule_dispute(
+    ctx: Context<RuleDispute>,
+    upheld: bool,
+    ruling_hash: [u8; 32],
+) -> Result<()> {
+    let dispute = &mut ctx.accounts.dispute;
+    let batch = &mut ctx.accounts.batch;
+    let regulator_profile = &ctx.accounts.regulator_profile;
+    let regulator = &ctx.accounts.regulator;
+    let clock = Clock::get()?;
+
+    require!(
+        regulator_profile.role == Role::Regulator,
+        SupplyChainError::InvalidRole
+    );
+    require!(
+        regulator_profile.is_approved,
+        SupplyChainError::UserNotApproved
+    );
+    require!(
+        dispute.status == DisputeStatus::Open,
+        SupplyChainError::DisputeNotOpen
+    );
+    require!(
+        ruling_hash != [0u8; 32],
+        SupplyChainError::InvalidDetailsHash
+    );
+
+    dispute.status = if upheld { DisputeStatus::Upheld } else { DisputeStatus::Rejected };
+    dispute.ruling_hash = ruling_hash;
+    dispute.resolved_at = clock.unix_timestamp;
+
+    // Compliance only comes back once no other breach in the history still
+    // stands; breaches that have rotated out of the window are not counted.
+    if upheld {
+        let history = &mut ctx.accounts.iot_history;
+        if let Some(entry) = history.entries.iter_mut().find(|entry| entry.iot_hash == dispute.iot_hash) {
+            entry.breach_overturned = true;
+        }
+        if !history.entries.iter().any(|entry| entry.breach_detected && !entry.breach_overturned) {
+            batch.compliance.cold_chain_compliant = true;
+        }
+    }
+
+    let ruling_event = Event {
+        event_type: EventType::DisputeResolved,
+        timestamp: clock.unix_timestamp,
+        from_wallet: regulator.key(),
+        to_wallet: dispute.opened_by,
+        details_hash: ruling_hash,
+        details_cid: String::new(),
+        location: None,
+    };
+
+    append_event(batch, &ctx.accounts.system_config.policy, ruling_event)?;
+
+    emit!(DisputeRuled {
+        batch_id: batch.id.clone(),
+        dispute: dispute.key(),
+        regulator: regulator.key(),
+        upheld,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
@@ -0,0 +1,218 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::SupplyChainError, events::BatchExpired, history::append_event, state::{Batch, BatchStatus, Event, EventType, SystemConfig, NOMINAL_DEGRADATION_RATE_BPS}};
+
+// Permissionless crank: anyone may move a batch past its expiry into `Expired`.
+#[derive(Accounts)]
+pub struct MarkExpired<'info> {
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    pub caller: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config: Account<'info, SystemConfig>,
+}
+
+pub fn _mark_expired(ctx: Context<MarkExpired>) -> Result<()> {
+    let batch = &mut ctx.accounts.batch;
+    let clock = Clock::get()?;
+
+    require!(
+        batch.status != BatchStatus::Recalled,
+        SupplyChainError::BatchAlreadyRecalled
+    );
+    require!(
+        !matches!(batch.status, BatchStatus::Expired | BatchStatus::Closed),
+        SupplyChainError::BatchAlreadyClosed
+    );
+    require!(
+        batch.status != BatchStatus::Flagged || batch.pre_flag_status != BatchStatus::Expired,
+        SupplyChainError::BatchAlreadyClosed
+    );
+    require!(
+        is_expired(batch, clock.unix_timestamp),
+        SupplyChainError::BatchNotExpired
+    );
+
+    // A flagged batch stays under review; resolving the flag restores it as
+    // expired rather than to the status it had before the flag.
+    if batch.status == BatchStatus::Flagged {
+        batch.pre_flag_status = BatchStatus::Expired;
+    } else {
+        batch.status = BatchStatus::Expired;
+    }
+
+    let expiry_event = Event {
+        event_type: EventType::Expiry,
+        timestamp: clock.unix_timestamp,
+        from_wallet: ctx.accounts.caller.key(),
+        to_wallet: batch.current_custodian,
+        details_hash: [0u8; 32],
+        details_cid: String::new(),
+        location: None,
+    };
+
+    append_event(batch, &ctx.accounts.system_config.policy, expiry_event)?;
+
+    emit!(BatchExpired {
+        batch_id: batch.id.clone(),
+        expiry_date: batch.origin_details.expiry_date,
+        effective_expiry: batch.effective_expiry,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+pub fn is_expired(batch: &Batch, now: i64) -> bool {
+    batch.status == BatchStatus::Expired || now >= batch.effective_expiry
+}
+
+/// Expired goods may only leave the chain through `mark_destroyed`; every
+/// instruction that moves, ships or sells a batch checks this first.
+pub fn _require_not_expired(batch: &Batch, now: i64) -> Result<()> {
+    require!(
+        !is_expired(batch, now),
+        SupplyChainError::BatchExpired
+    );
+    Ok(())
+}
+
+// Charges the time since the last update against the remaining shelf life at
+// the rate of the band the summary's average temperature falls in (the
+// hottest band above the table, nominal pace with no table). Time spent in a
+// reported excursion is instead charged at `excursion_shelf_life_factor`
+// seconds per second when that is steeper than the band, so it is never
+// counted twice. The effective expiry is then brought forward if the batch
+// will now run out sooner.
+pub fn _degrade_shelf_life(batch: &mut Batch, avg_temp_centi_c: i32, excursion_seconds: u64, now: i64) {
+    let elapsed = now.saturating_sub(batch.shelf_life_updated_at);
+    if elapsed <= 0 {
+        return;
+    }
+
+    let rate_bps = batch
+        .degradation
+        .iter()
+        .find(|band| avg_temp_centi_c <= band.max_temp_centi_c)
+        .or(batch.degradation.last())
+        .map_or(NOMINAL_DEGRADATION_RATE_BPS, |band| band.rate_bps) as i128;
+    let excursion_rate_bps = rate_bps.max(batch.threshold.excursion_shelf_life_factor as i128 * NOMINAL_DEGRADATION_RATE_BPS as i128);
+    let excursion = excursion_seconds.min(elapsed as u64) as i128;
+    let consumed = (((elapsed as i128 - excursion) * rate_bps + excursion * excursion_rate_bps)
+        / NOMINAL_DEGRADATION_RATE_BPS as i128)
+        .min(i64::MAX as i128) as i64;
+
+    batch.remaining_shelf_life_seconds = batch.remaining_shelf_life_seconds.saturating_sub(consumed).max(0);
+    batch.shelf_life_updated_at = now;
+    batch.effective_expiry = batch
+        .effective_expiry
+        .min(now.saturating_add(batch.remaining_shelf_life_seconds));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::DegradationBand;
+
+    const START: i64 = 1_700_000_000;
+    const SHELF_LIFE: i64 = 10 * 24 * 3600;
+
+    // An all-zero account image decodes to an empty batch; only the shelf-life
+    // fields matter here.
+    fn batch(degradation: Vec<DegradationBand>) -> Batch {
+        let zeroed = vec![0u8; Batch::INIT_SPACE];
+        let mut batch = Batch::deserialize(&mut zeroed.as_slice()).unwrap();
+        batch.remaining_shelf_life_seconds = SHELF_LIFE;
+        batch.shelf_life_updated_at = START;
+        batch.effective_expiry = START + SHELF_LIFE;
+        batch.degradation = degradation;
+        batch
+    }
+
+    fn bands() -> Vec<DegradationBand> {
+        vec![
+            DegradationBand { max_temp_centi_c: 400, rate_bps: 5_000 },
+            DegradationBand { max_temp_centi_c: 800, rate_bps: 10_000 },
+            DegradationBand { max_temp_centi_c: 1_500, rate_bps: 40_000 },
+        ]
+    }
+
+    #[test]
+    fn charges_at_the_rate_of_the_first_matching_band() {
+        let mut cold = batch(bands());
+        _degrade_shelf_life(&mut cold, 400, 0, START + 1_000);
+        assert_eq!(cold.remaining_shelf_life_seconds, SHELF_LIFE - 500);
+        assert_eq!(cold.shelf_life_updated_at, START + 1_000);
+        assert_eq!(cold.effective_expiry, START + SHELF_LIFE);
+
+        let mut warm = batch(bands());
+        _degrade_shelf_life(&mut warm, 1_000, 0, START + 1_000);
+        assert_eq!(warm.remaining_shelf_life_seconds, SHELF_LIFE - 4_000);
+        assert_eq!(warm.effective_expiry, START + SHELF_LIFE - 3_000);
+    }
+
+    #[test]
+    fn charges_the_hottest_rate_above_the_table() {
+        let mut batch = batch(bands());
+        _degrade_shelf_life(&mut batch, 3_000, 0, START + 1_000);
+        assert_eq!(batch.remaining_shelf_life_seconds, SHELF_LIFE - 4_000);
+    }
+
+    #[test]
+    fn runs_at_nominal_pace_without_a_table() {
+        let mut batch = batch(Vec::new());
+        _degrade_shelf_life(&mut batch, 3_000, 0, START + 1_000);
+        assert_eq!(batch.remaining_shelf_life_seconds, SHELF_LIFE - 1_000);
+        assert_eq!(batch.effective_expiry, START + SHELF_LIFE);
+    }
+
+    #[test]
+    fn ignores_summaries_that_do_not_advance_the_clock() {
+        for now in [START, START - 60] {
+            let mut batch = batch(bands());
+            _degrade_shelf_life(&mut batch, 3_000, 0, now);
+            assert_eq!(batch.remaining_shelf_life_seconds, SHELF_LIFE);
+            assert_eq!(batch.shelf_life_updated_at, START);
+            assert_eq!(batch.effective_expiry, START + SHELF_LIFE);
+        }
+    }
+
+    #[test]
+    fn charges_excursions_at_the_steeper_of_factor_and_band() {
+        // 200 of 1 000 s in an excursion at 4x: 800 * 0.5 + 200 * 4.
+        let mut cold = batch(bands());
+        cold.threshold.excursion_shelf_life_factor = 4;
+        _degrade_shelf_life(&mut cold, 400, 200, START + 1_000);
+        assert_eq!(cold.remaining_shelf_life_seconds, SHELF_LIFE - 1_200);
+
+        // A band already steeper than the factor is not charged again.
+        let mut warm = batch(bands());
+        warm.threshold.excursion_shelf_life_factor = 2;
+        _degrade_shelf_life(&mut warm, 1_000, 200, START + 1_000);
+        assert_eq!(warm.remaining_shelf_life_seconds, SHELF_LIFE - 4_000);
+
+        // The excursion window is capped at the time being charged.
+        let mut capped = batch(Vec::new());
+        capped.threshold.excursion_shelf_life_factor = 3;
+        _degrade_shelf_life(&mut capped, 400, 5_000, START + 1_000);
+        assert_eq!(capped.remaining_shelf_life_seconds, SHELF_LIFE - 3_000);
+        assert_eq!(capped.effective_expiry, START + SHELF_LIFE - 2_000);
+    }
+
+    #[test]
+    fn never_runs_below_zero() {
+        let mut batch = batch(bands());
+        _degrade_shelf_life(&mut batch, 3_000, 0, START + SHELF_LIFE);
+        assert_eq!(batch.remaining_shelf_life_seconds, 0);
+        assert_eq!(batch.effective_expiry, START + SHELF_LIFE);
+    }
+}
@@ -0,0 +1,163 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::SupplyChainError, events::{FlagEscalated, FlagResolved}, history::append_event, policy::allowed_cid, state::{Batch, BatchStatus, Event, EventType, PolicyConfig, Role, SystemConfig, UserProfile, DETAILS_CID_LENGTH}};
+
+#[derive(Accounts)]
+pub struct ResolveFlag<'info> {
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(
+        seeds = [b"user", regulator.key().as_ref()],
+        bump = regulator_profile.bump
+    )]
+    pub regulator_profile: Account<'info, UserProfile>,
+
+    #[account(mut)]
+    pub regulator: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config: Account<'info, SystemConfig>,
+}
+
+#[derive(Accounts)]
+pub struct EscalateFlag<'info> {
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(
+        seeds = [b"user", regulator.key().as_ref()],
+        bump = regulator_profile.bump
+    )]
+    pub regulator_profile: Account<'info, UserProfile>,
+
+    #[account(mut)]
+    pub regulator: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config: Account<'info, SystemConfig>,
+}
+
+pub fn _resolve_flag(
+    ctx: Context<ResolveFlag>,
+    report_hash: [u8; 32],
+    report_cid: String,
+) -> Result<()> {
+    let batch = &mut ctx.accounts.batch;
+    let regulator = &ctx.accounts.regulator;
+    let clock = Clock::get()?;
+
+    let report_cid = _validate_flag_review(batch, &ctx.accounts.system_config.policy, &ctx.accounts.regulator_profile, &regulator.key(), &report_hash, &report_cid)?;
+
+    batch.status = batch.pre_flag_status.clone();
+
+    let resolve_event = Event {
+        event_type: EventType::FlagResolved,
+        timestamp: clock.unix_timestamp,
+        from_wallet: regulator.key(),
+        to_wallet: regulator.key(),
+        details_hash: report_hash,
+        details_cid: report_cid,
+        location: None,
+    };
+
+    append_event(batch, &ctx.accounts.system_config.policy, resolve_event)?;
+
+    emit!(FlagResolved {
+        batch_id: batch.id.clone(),
+        regulator: regulator.key(),
+        restored_status: batch.status.clone(),
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+pub fn _escalate_flag(
+    ctx: Context<EscalateFlag>,
+    report_hash: [u8; 32],
+    report_cid: String,
+) -> Result<()> {
+    let batch = &mut ctx.accounts.batch;
+    let regulator = &ctx.accounts.regulator;
+    let clock = Clock::get()?;
+
+    let report_cid = _validate_flag_review(batch, &ctx.accounts.system_config.policy, &ctx.accounts.regulator_profile, &regulator.key(), &report_hash, &report_cid)?;
+
+    batch.status = BatchStatus::Recalled;
+
+    let escalate_event = Event {
+        event_type: EventType::FlagEscalated,
+        timestamp: clock.unix_timestamp,
+        from_wallet: regulator.key(),
+        to_wallet: regulator.key(),
+        details_hash: report_hash,
+        details_cid: report_cid,
+        location: None,
+    };
+
+    append_event(batch, &ctx.accounts.system_config.policy, escalate_event)?;
+
+    emit!(FlagEscalated {
+        batch_id: batch.id.clone(),
+        regulator: regulator.key(),
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Moves a batch into `Flagged`, remembering the status it had before so a
+/// regulator can restore it with `resolve_flag`.
+pub fn _mark_flagged(batch: &mut Batch) {
+    if batch.status != BatchStatus::Flagged {
+        batch.pre_flag_status = batch.status.clone();
+        batch.status = BatchStatus::Flagged;
+    }
+}
+
+fn _validate_flag_review(
+    batch: &Batch,
+    policy: &PolicyConfig,
+    regulator_profile: &UserProfile,
+    regulator: &Pubkey,
+    report_hash: &[u8; 32],
+    report_cid: &str,
+) -> Result<String> {
+    require!(
+        regulator_profile.role == Role::Regulator,
+        SupplyChainError::InvalidRole
+    );
+    require!(
+        regulator_profile.is_approved,
+        SupplyChainError::UserNotApproved
+    );
+    require!(
+        *regulator == regulator_profile.user_wallet,
+        SupplyChainError::WalletMismatch
+    );
+    require!(
+        batch.status == BatchStatus::Flagged,
+        SupplyChainError::BatchNotFlagged
+    );
+    require!(
+        *report_hash != [0u8; 32],
+        SupplyChainError::InvalidDetailsHash
+    );
+    let report_cid = allowed_cid(policy, report_cid, DETAILS_CID_LENGTH).ok_or(SupplyChainError::InvalidDetailsCid)?;
+    Ok(report_cid)
+}
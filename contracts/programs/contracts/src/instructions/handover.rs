@@ -1,21 +1,25 @@
 use anchor_lang::prelude::*;
 
-use crate::{errors::SupplyChainError, events::HandoverLogged, state::{Batch, BatchStatus, Event, EventType, Role, UserProfile, DETAILS_CID_LENGTH, EVENT_LENGTH}};
+use crate::{errors::SupplyChainError, events::{HandoverLogged, OwnershipTransferred}, history::append_event, instructions::{_receive_shipment, _require_not_expired, validate_role_transition}, policy::allowed_cid, state::{Batch, BatchStatus, Event, EventType, LegSummary, Role, RoleTransitions, ShipmentPlan, SystemConfig, UserProfile, DETAILS_CID_LENGTH}};
 
 #[derive(Accounts)]
 pub struct LogHandover<'info> {
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
     pub batch: Account<'info, Batch>,
     
     #[account(
         seeds = [b"user", from_user.key().as_ref()],
-        bump
+        bump = from_user_profile.bump
     )]
     pub from_user_profile: Account<'info, UserProfile>,
     
     #[account(
         seeds = [b"user", to_user.key().as_ref()],
-        bump
+        bump = to_user_profile.bump
     )]
     pub to_user_profile: Account<'info, UserProfile>,
     
@@ -24,15 +28,130 @@ pub struct LogHandover<'info> {
     
     #[account(mut)]
     pub to_user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"leg", batch.key().as_ref(), &batch.current_leg.to_le_bytes()],
+        bump = current_leg.bump
+    )]
+    pub current_leg: Account<'info, LegSummary>,
+
+    #[account(
+        init,
+        payer = from_user,
+        space = 8 + LegSummary::INIT_SPACE,
+        seeds = [b"leg", batch.key().as_ref(), &(batch.current_leg + 1).to_le_bytes()],
+        bump
+    )]
+    pub next_leg: Account<'info, LegSummary>,
+
+    #[account(
+        mut,
+        seeds = [b"shipment", batch.key().as_ref()],
+        bump = shipment_plan.bump
+    )]
+    pub shipment_plan: Option<Account<'info, ShipmentPlan>>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config: Account<'info, SystemConfig>,
+
+    #[account(
+        seeds = [b"role_transitions"],
+        bump = role_transitions.bump
+    )]
+    pub role_transitions: Account<'info, RoleTransitions>,
+
+    pub system_program: Program<'info, System>,
 }
 
 
 
+#[derive(Accounts)]
+pub struct TransferOwnership<'info> {
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(
+        seeds = [b"user", from_user.key().as_ref()],
+        bump = from_user_profile.bump
+    )]
+    pub from_user_profile: Account<'info, UserProfile>,
+
+    #[account(
+        seeds = [b"user", to_user.key().as_ref()],
+        bump = to_user_profile.bump
+    )]
+    pub to_user_profile: Account<'info, UserProfile>,
+
+    pub from_user: Signer<'info>,
+
+    pub to_user: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config: Account<'info, SystemConfig>,
+
+    #[account(
+        seeds = [b"role_transitions"],
+        bump = role_transitions.bump
+    )]
+    pub role_transitions: Account<'info, RoleTransitions>,
+}
+
+// A full handover moves title and custody together, so the owner must also be
+// holding the batch.
 pub fn _log_handover(
         ctx: Context<LogHandover>,
         to_wallet: Pubkey,
         details_hash: [u8; 32],
         details_cid: String,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.from_user.key() == ctx.accounts.batch.current_owner,
+            SupplyChainError::NotCurrentOwner
+        );
+        require!(
+            ctx.accounts.batch.current_custodian == ctx.accounts.batch.current_owner,
+            SupplyChainError::NotCurrentCustodian
+        );
+
+        let clock = Clock::get()?;
+        _move_custody(ctx, EventType::HandOver, to_wallet, details_hash, details_cid, clock.unix_timestamp)?;
+        Ok(())
+}
+
+// Hands physical custody to a carrier, warehouse or the next site while the
+// owner keeps title. Uses the same accounts as `log_handover`.
+pub fn _transfer_custody(
+        ctx: Context<LogHandover>,
+        to_wallet: Pubkey,
+        details_hash: [u8; 32],
+        details_cid: String,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.from_user.key() == ctx.accounts.batch.current_custodian,
+            SupplyChainError::NotCurrentCustodian
+        );
+
+        let clock = Clock::get()?;
+        _move_custody(ctx, EventType::CustodyTransfer, to_wallet, details_hash, details_cid, clock.unix_timestamp)?;
+        Ok(())
+}
+
+// Sells or assigns title while the goods stay where they are.
+pub fn _transfer_ownership(
+        ctx: Context<TransferOwnership>,
+        to_wallet: Pubkey,
+        details_hash: [u8; 32],
     ) -> Result<()> {
         let batch = &mut ctx.accounts.batch;
         let from_user_profile = &ctx.accounts.from_user_profile;
@@ -42,16 +161,76 @@ pub fn _log_handover(
         let clock = Clock::get()?;
 
         require!(
-            from_user_profile.is_approved,
+            from_user.key() == batch.current_owner,
+            SupplyChainError::NotCurrentOwner
+        );
+        require!(
+            from_user_profile.is_approved && to_user_profile.is_approved,
             SupplyChainError::UserNotApproved
         );
         require!(
-            to_user_profile.is_approved,
+            from_user_profile.user_wallet == from_user.key() && to_user.key() == to_wallet,
+            SupplyChainError::WalletMismatch
+        );
+        validate_role_transition(&ctx.accounts.role_transitions, &from_user_profile.role, &to_user_profile.role)?;
+        require!(
+            !matches!(batch.status, BatchStatus::Flagged | BatchStatus::Recalled | BatchStatus::Closed),
+            SupplyChainError::BatchNotCompliant
+        );
+        _require_not_expired(batch, clock.unix_timestamp)?;
+        require!(
+            details_hash != [0u8; 32],
+            SupplyChainError::InvalidDetailsHash
+        );
+
+        let event = Event {
+            event_type: EventType::OwnershipTransfer,
+            timestamp: clock.unix_timestamp,
+            from_wallet: batch.current_owner,
+            to_wallet,
+            details_hash,
+            details_cid: String::new(),
+            location: None,
+        };
+
+        append_event(batch, &ctx.accounts.system_config.policy, event)?;
+
+        batch.current_owner = to_wallet;
+
+        emit!(OwnershipTransferred {
+            batch_id: batch.id.clone(),
+            from_wallet: from_user.key(),
+            to_wallet,
+            custodian: batch.current_custodian,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+}
+
+// Moves custody to `to_wallet`, closing the current leg and opening the next.
+// Title follows only for a full handover.
+fn _move_custody(
+        ctx: Context<LogHandover>,
+        event_type: EventType,
+        to_wallet: Pubkey,
+        details_hash: [u8; 32],
+        details_cid: String,
+        now: i64,
+    ) -> Result<()> {
+        let batch = &mut ctx.accounts.batch;
+        let from_user_profile = &ctx.accounts.from_user_profile;
+        let to_user_profile = &ctx.accounts.to_user_profile;
+        let from_user = &ctx.accounts.from_user;
+        let to_user = &ctx.accounts.to_user;
+
+        require!(
+            from_user_profile.is_approved,
             SupplyChainError::UserNotApproved
         );
         require!(
-            from_user.key() == batch.current_owner,
-            SupplyChainError::NotCurrentOwner
+            to_user_profile.is_approved,
+            SupplyChainError::UserNotApproved
         );
         require!(
             from_user_profile.user_wallet == from_user.key(),
@@ -66,84 +245,87 @@ pub fn _log_handover(
             SupplyChainError::WalletMismatch
         );
         require!(
-            batch.status != BatchStatus::Flagged && batch.status != BatchStatus::Recalled,
+            !matches!(batch.status, BatchStatus::Flagged | BatchStatus::Recalled | BatchStatus::Closed),
             SupplyChainError::BatchNotCompliant
         );
+        _require_not_expired(batch, now)?;
+        let full_handover = event_type == EventType::HandOver;
         require!(
-            details_hash != [0u8; 32],
-            SupplyChainError::InvalidDetailsHash
+            !full_handover || batch.handover_count < ctx.accounts.system_config.policy.max_handovers,
+            SupplyChainError::TooManyHandovers
         );
         require!(
-            !details_cid.is_empty() && details_cid.len() <= DETAILS_CID_LENGTH,
-            SupplyChainError::InvalidDetailsCid
+            details_hash != [0u8; 32],
+            SupplyChainError::InvalidDetailsHash
         );
+        let details_cid = allowed_cid(&ctx.accounts.system_config.policy, &details_cid, DETAILS_CID_LENGTH).ok_or(SupplyChainError::InvalidDetailsCid)?;
 
-        _validate_role_transition(&from_user_profile.role, &to_user_profile.role)?;
-
-        match from_user_profile.role {
-            Role::Producer | Role::Processor | Role::Distributor => {},
-            _ => return Err(SupplyChainError::InvalidHandoverRole.into()),
-        }
+        validate_role_transition(&ctx.accounts.role_transitions, &from_user_profile.role, &to_user_profile.role)?;
+        _receive_shipment(batch, ctx.accounts.shipment_plan.as_mut(), from_user.to_account_info(), &to_wallet, now)?;
 
         let event = Event {
-            event_type: EventType::HandOver,
-            timestamp: clock.unix_timestamp,
-            from_wallet: batch.current_owner,
+            event_type,
+            timestamp: now,
+            from_wallet: batch.current_custodian,
             to_wallet,
             details_hash,
             details_cid,
+            location: None,
         };
 
-        require!(
-            batch.events.len() < EVENT_LENGTH,
-            SupplyChainError::TooManyEvents
-        );
-        batch.events.push(event);
+        append_event(batch, &ctx.accounts.system_config.policy, event)?;
 
-        batch.current_owner = to_wallet;
+        if full_handover {
+            batch.current_owner = to_wallet;
+            batch.handover_count += 1;
+        }
+        batch.current_custodian = to_wallet;
 
-        batch.status = match to_user_profile.role {
-            Role::Processor => BatchStatus::InProcessing,
-            Role::Distributor => BatchStatus::InTransit,
-            Role::Retailer => BatchStatus::Sold,
-            Role::Consumer => BatchStatus::Sold,
-            _ => BatchStatus::InTransit,
-        };
+        ctx.accounts.current_leg.ended_at = now;
+        batch.current_leg += 1;
+        _open_leg(
+            &mut ctx.accounts.next_leg,
+            batch.key(),
+            batch.current_leg,
+            to_wallet,
+            from_user.key(),
+            now,
+            ctx.bumps.next_leg,
+        );
+
+        // The lifecycle stage follows whoever takes title; a carrier or
+        // warehouse holding the goods for the owner leaves it unchanged.
+        if full_handover {
+            batch.status = match to_user_profile.role {
+                Role::Processor => BatchStatus::InProcessing,
+                Role::Distributor => BatchStatus::InTransit,
+                Role::Transporter => BatchStatus::InTransit,
+                Role::Warehouse => BatchStatus::InStorage,
+                Role::Retailer => BatchStatus::Sold,
+                Role::Consumer => BatchStatus::Sold,
+                _ => BatchStatus::InTransit,
+            };
+        }
 
         emit!(HandoverLogged {
             batch_id: batch.id.clone(),
             from_wallet: from_user.key(),
             to_wallet,
-            timestamp: clock.unix_timestamp,
+            timestamp: now,
         });
 
         Ok(())
 }
 
-fn _validate_role_transition(from_role: &Role, to_role: &Role) -> Result<()> {
-    match from_role {
-        Role::Producer => {
-            require!(
-                matches!(to_role, Role::Processor | Role::Distributor | Role::Retailer),
-                SupplyChainError::InvalidRoleTransition
-            );
-        },
-        Role::Processor => {
-            require!(
-                matches!(to_role, Role::Distributor | Role::Retailer),
-                SupplyChainError::InvalidRoleTransition
-            );
-        },
-        Role::Distributor => {
-            require!(
-                matches!(to_role, Role::Retailer),
-                SupplyChainError::InvalidRoleTransition
-            );
-        },
-        _ => return Err(SupplyChainError::InvalidRoleTransition.into()),
-    }
-    Ok(())
+pub fn _open_leg(leg: &mut LegSummary, batch: Pubkey, leg_index: u16, custodian: Pubkey, payer: Pubkey, started_at: i64, bump: u8) {
+    leg.batch = batch;
+    leg.leg_index = leg_index;
+    leg.custodian = custodian;
+    leg.started_at = started_at;
+    leg.ended_at = 0;
+    leg.summary_count = 0;
+    leg.excursion_count = 0;
+    leg.excursion_seconds = 0;
+    leg.payer = payer;
+    leg.bump = bump;
 }
-
-
-
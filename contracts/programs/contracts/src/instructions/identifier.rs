@@ -0,0 +1,131 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::SupplyChainError, events::{Gs1LotAssigned, LogisticUnitRegistered}, gs1::{normalize_gtin, valid_lot, valid_sscc}, state::{Batch, BatchStatus, LogisticUnit, LotLookup, Product}};
+
+#[derive(Accounts)]
+#[instruction(lot: String)]
+pub struct AssignGs1Lot<'info> {
+    // An unnumbered SKU seeds with an empty GTIN; the handler rejects it.
+    #[account(
+        init,
+        payer = producer,
+        space = 8 + LotLookup::INIT_SPACE,
+        seeds = [b"lot", normalize_gtin(&product.sku).unwrap_or_default().as_bytes(), lot.as_bytes()],
+        bump
+    )]
+    pub lot_lookup: Account<'info, LotLookup>,
+
+    #[account(
+        mut,
+        has_one = producer,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(
+        address = batch.origin_details.product
+    )]
+    pub product: Account<'info, Product>,
+
+    #[account(mut)]
+    pub producer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(sscc: String)]
+pub struct RegisterLogisticUnit<'info> {
+    #[account(
+        init,
+        payer = custodian,
+        space = 8 + LogisticUnit::INIT_SPACE,
+        seeds = [b"sscc", sscc.as_bytes()],
+        bump
+    )]
+    pub logistic_unit: Account<'info, LogisticUnit>,
+
+    #[account(
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(mut)]
+    pub custodian: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// The GTIN comes from the batch's product, whose SKU must be the GTIN itself.
+// Lots are only unique per GTIN, so only the brand owner may assign them;
+// otherwise any producer could take another brand's lot numbers first.
+pub fn _assign_gs1_lot(ctx: Context<AssignGs1Lot>, lot: String) -> Result<()> {
+    let batch = &mut ctx.accounts.batch;
+
+    require!(
+        ctx.accounts.product.brand_owner == ctx.accounts.producer.key(),
+        SupplyChainError::NotBrandOwner
+    );
+    require!(
+        batch.gs1_lot.is_empty(),
+        SupplyChainError::LotAlreadyAssigned
+    );
+    require!(
+        valid_lot(&lot),
+        SupplyChainError::InvalidLotNumber
+    );
+    let gtin = normalize_gtin(&ctx.accounts.product.sku).ok_or(SupplyChainError::InvalidGtin)?;
+
+    let lookup = &mut ctx.accounts.lot_lookup;
+    lookup.gtin = gtin.clone();
+    lookup.lot = lot.clone();
+    lookup.batch = batch.key();
+    lookup.bump = ctx.bumps.lot_lookup;
+
+    batch.gs1_lot = lot.clone();
+
+    emit!(Gs1LotAssigned {
+        batch_id: batch.id.clone(),
+        gtin,
+        lot,
+    });
+
+    Ok(())
+}
+
+pub fn _register_logistic_unit(ctx: Context<RegisterLogisticUnit>, sscc: String) -> Result<()> {
+    let batch = &ctx.accounts.batch;
+    let custodian = &ctx.accounts.custodian;
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(
+        custodian.key() == batch.current_custodian,
+        SupplyChainError::NotCurrentCustodian
+    );
+    require!(
+        batch.status != BatchStatus::Closed,
+        SupplyChainError::BatchAlreadyClosed
+    );
+    require!(
+        valid_sscc(&sscc),
+        SupplyChainError::InvalidSscc
+    );
+
+    let unit = &mut ctx.accounts.logistic_unit;
+    unit.sscc = sscc.clone();
+    unit.batch = batch.key();
+    unit.registered_by = custodian.key();
+    unit.registered_at = now;
+    unit.bump = ctx.bumps.logistic_unit;
+
+    emit!(LogisticUnitRegistered {
+        batch_id: batch.id.clone(),
+        sscc,
+        registered_by: custodian.key(),
+        timestamp: now,
+    });
+
+    Ok(())
+}
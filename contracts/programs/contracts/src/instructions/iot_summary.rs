@@ -0,0 +1,298 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::SupplyChainError, events::BreachAttributed, geo::{validate_geo_point, waypoint_of}, history::append_event, instructions::{_apply_compliance_report, _build_compliance_report, _check_route, _degrade_shelf_life, _mark_flagged}, policy::allowed_cid, state::{Batch, BatchStatus, Event, EventType, IoTHistory, IoTHistoryEntry, IoTSummaryStruct, LegSummary, RouteCorridor, SystemConfig, IOT_CID_LENGTH, IOT_HISTORY_LENGTH, MAX_HUMIDITY_BPS, MAX_TEMP_CENTI_C, MIN_TEMP_CENTI_C}};
+
+#[derive(Accounts)]
+pub struct UpdateIotSummary<'info> {
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(
+        mut,
+        seeds = [b"iot_history", batch.key().as_ref()],
+        bump = iot_history.bump
+    )]
+    pub iot_history: Account<'info, IoTHistory>,
+
+    #[account(
+        mut,
+        seeds = [b"leg", batch.key().as_ref(), &batch.current_leg.to_le_bytes()],
+        bump = leg_summary.bump
+    )]
+    pub leg_summary: Account<'info, LegSummary>,
+
+    #[account(
+        seeds = [b"route", batch.key().as_ref()],
+        bump = route.bump
+    )]
+    pub route: Option<Account<'info, RouteCorridor>>,
+    
+    #[account(mut)]
+    pub oracle: Signer<'info>,
+    
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config: Account<'info, SystemConfig>,
+}
+
+
+pub fn _update_iot_summary(
+    ctx: Context<UpdateIotSummary>,
+    summary: IoTSummaryStruct,
+    new_hash: [u8; 32],
+    new_cid: String,
+) -> Result<()> {
+    let batch = &mut ctx.accounts.batch;
+    let oracle = &ctx.accounts.oracle;
+    let system_config = &ctx.accounts.system_config;
+    let clock = Clock::get()?;
+
+    require!(
+        oracle.key() == system_config.oracle_wallet,
+        SupplyChainError::UnauthorizedOracle
+    );
+
+    _validate_iot_summary(&summary, batch.iot_summary.timestamp, clock.unix_timestamp, system_config.policy.max_clock_drift_seconds)?;
+
+    require!(
+        new_hash != [0u8; 32],
+        SupplyChainError::InvalidDetailsHash
+    );
+    let new_cid = allowed_cid(&system_config.policy, &new_cid, IOT_CID_LENGTH).ok_or(SupplyChainError::InvalidDetailsCid)?;
+
+    require!(
+        batch.status != BatchStatus::Recalled && batch.status != BatchStatus::Closed,
+        SupplyChainError::BatchNotCompliant
+    );
+
+    let custodian = batch.current_custodian;
+    _record_iot_history(&mut ctx.accounts.iot_history, &summary, new_hash, &new_cid, custodian, batch.current_leg);
+    let excursion_seconds = _record_leg_summary(&mut ctx.accounts.leg_summary, &summary, batch.iot_summary.timestamp);
+
+    _degrade_shelf_life(batch, summary.avg_temp_centi_c, excursion_seconds, summary.timestamp);
+    batch.iot_summary = summary.clone();
+    batch.iot_hash = new_hash;
+    batch.iot_cid = new_cid;
+
+    if let Some(location) = &summary.location {
+        _check_route(
+            batch,
+            ctx.accounts.route.as_deref(),
+            &system_config.policy,
+            location,
+            oracle.key(),
+            clock.unix_timestamp,
+        )?;
+    }
+
+    // `breach_count` arrives with the summary; it is not counted again here.
+    if summary.breach_detected {
+        _mark_flagged(batch);
+        batch.compliance.cold_chain_compliant = false;
+
+        // The event points at the summary that reported the breach; the leg it
+        // is attributed to lives in the LegSummary and the BreachAttributed
+        // emit below.
+        let breach_event = Event {
+            event_type: EventType::BreachDetected,
+            timestamp: clock.unix_timestamp,
+            from_wallet: oracle.key(),
+            to_wallet: custodian,
+            details_hash: new_hash,
+            details_cid: String::new(), 
+            location: summary.location.as_ref().map(waypoint_of),
+        };
+
+        append_event(batch, &system_config.policy, breach_event)?;
+
+        emit!(BreachAttributed {
+            batch_id: batch.id.clone(),
+            custodian,
+            leg_index: batch.current_leg,
+            excursion_seconds,
+            timestamp: clock.unix_timestamp,
+        });
+
+        let report = _build_compliance_report(
+            batch,
+            &ctx.accounts.iot_history,
+            system_config.policy.iot_staleness_seconds,
+            clock.unix_timestamp,
+        );
+        _apply_compliance_report(batch, &system_config.policy, &report, &system_config.oracle_wallet, clock.unix_timestamp)?;
+    }
+
+    Ok(())
+}
+
+fn _record_iot_history(
+    history: &mut IoTHistory,
+    summary: &IoTSummaryStruct,
+    iot_hash: [u8; 32],
+    iot_cid: &str,
+    custodian: Pubkey,
+    leg_index: u16,
+) {
+    let entry = IoTHistoryEntry {
+        timestamp: summary.timestamp,
+        iot_hash,
+        iot_cid: iot_cid.to_string(),
+        min_temp_centi_c: summary.min_temp_centi_c,
+        max_temp_centi_c: summary.max_temp_centi_c,
+        avg_temp_centi_c: summary.avg_temp_centi_c,
+        min_humidity_bps: summary.min_humidity_bps,
+        max_humidity_bps: summary.max_humidity_bps,
+        avg_humidity_bps: summary.avg_humidity_bps,
+        breach_detected: summary.breach_detected,
+        breach_overturned: false,
+        custodian,
+        leg_index,
+    };
+
+    if history.entries.len() < IOT_HISTORY_LENGTH {
+        history.entries.push(entry);
+    } else {
+        history.entries[history.head as usize] = entry;
+    }
+    history.head = ((history.head as usize + 1) % IOT_HISTORY_LENGTH) as u8;
+}
+
+// Counts the summary against the current custodian's leg. A breach window is
+// taken to span from the previous summary (or the start of the leg, whichever
+// is later) to this one; returns that excursion duration in seconds.
+fn _record_leg_summary(leg: &mut LegSummary, summary: &IoTSummaryStruct, previous_timestamp: i64) -> u64 {
+    leg.summary_count = leg.summary_count.saturating_add(1);
+    if !summary.breach_detected {
+        return 0;
+    }
+
+    let window_start = previous_timestamp.max(leg.started_at);
+    let excursion_seconds = summary.timestamp.saturating_sub(window_start).max(0) as u64;
+    leg.excursion_count = leg.excursion_count.saturating_add(1);
+    leg.excursion_seconds = leg.excursion_seconds.saturating_add(excursion_seconds);
+    excursion_seconds
+}
+
+fn _validate_iot_summary(summary: &IoTSummaryStruct, previous_timestamp: i64, now: i64, max_clock_drift_seconds: i64) -> Result<()> {
+    require!(
+        summary.timestamp > previous_timestamp,
+        SupplyChainError::InvalidTimestamp
+    );
+    require!(
+        summary.timestamp <= now.saturating_add(max_clock_drift_seconds),
+        SupplyChainError::FutureTimestamp
+    );
+
+    require!(
+        [summary.min_temp_centi_c, summary.max_temp_centi_c, summary.avg_temp_centi_c]
+            .iter()
+            .all(|t| (MIN_TEMP_CENTI_C..=MAX_TEMP_CENTI_C).contains(t)),
+        SupplyChainError::InvalidTemperatureValue
+    );
+    require!(
+        summary.min_temp_centi_c <= summary.max_temp_centi_c,
+        SupplyChainError::InvalidTemperatureRange
+    );
+    require!(
+        (summary.min_temp_centi_c..=summary.max_temp_centi_c).contains(&summary.avg_temp_centi_c),
+        SupplyChainError::AverageTemperatureOutOfRange
+    );
+
+    require!(
+        [summary.min_humidity_bps, summary.max_humidity_bps, summary.avg_humidity_bps]
+            .iter()
+            .all(|h| *h <= MAX_HUMIDITY_BPS),
+        SupplyChainError::InvalidHumidityValue
+    );
+    require!(
+        summary.min_humidity_bps <= summary.max_humidity_bps,
+        SupplyChainError::InvalidHumidityRange
+    );
+    require!(
+        (summary.min_humidity_bps..=summary.max_humidity_bps).contains(&summary.avg_humidity_bps),
+        SupplyChainError::AverageHumidityOutOfRange
+    );
+
+    require!(
+        summary.breach_detected == (summary.breach_count > 0),
+        SupplyChainError::BreachCountMismatch
+    );
+
+    if let Some(location) = &summary.location {
+        validate_geo_point(location)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+    const DRIFT: i64 = 300;
+
+    fn summary() -> IoTSummaryStruct {
+        IoTSummaryStruct {
+            timestamp: NOW,
+            min_temp_centi_c: 200,
+            max_temp_centi_c: 800,
+            avg_temp_centi_c: 500,
+            min_humidity_bps: 4_000,
+            max_humidity_bps: 6_000,
+            avg_humidity_bps: 5_000,
+            location_summary: String::new(),
+            breach_detected: false,
+            breach_count: 0,
+            location: None,
+        }
+    }
+
+    fn validate(summary: &IoTSummaryStruct) -> Result<()> {
+        _validate_iot_summary(summary, NOW - 60, NOW, DRIFT)
+    }
+
+    #[test]
+    fn accepts_a_consistent_summary() {
+        assert!(validate(&summary()).is_ok());
+    }
+
+    #[test]
+    fn rejects_average_outside_min_max() {
+        let temp = IoTSummaryStruct { avg_temp_centi_c: 900, ..summary() };
+        assert_eq!(validate(&temp).unwrap_err(), SupplyChainError::AverageTemperatureOutOfRange.into());
+
+        let humidity = IoTSummaryStruct { avg_humidity_bps: 3_999, ..summary() };
+        assert_eq!(validate(&humidity).unwrap_err(), SupplyChainError::AverageHumidityOutOfRange.into());
+    }
+
+    #[test]
+    fn rejects_humidity_above_full_scale() {
+        let humidity = IoTSummaryStruct { max_humidity_bps: MAX_HUMIDITY_BPS + 1, ..summary() };
+        assert_eq!(validate(&humidity).unwrap_err(), SupplyChainError::InvalidHumidityValue.into());
+    }
+
+    #[test]
+    fn rejects_breach_flag_and_count_disagreeing() {
+        let flag_only = IoTSummaryStruct { breach_detected: true, ..summary() };
+        assert_eq!(validate(&flag_only).unwrap_err(), SupplyChainError::BreachCountMismatch.into());
+
+        let count_only = IoTSummaryStruct { breach_count: 2, ..summary() };
+        assert_eq!(validate(&count_only).unwrap_err(), SupplyChainError::BreachCountMismatch.into());
+    }
+
+    #[test]
+    fn rejects_timestamps_beyond_clock_drift() {
+        let at_limit = IoTSummaryStruct { timestamp: NOW + DRIFT, ..summary() };
+        assert!(validate(&at_limit).is_ok());
+
+        let future = IoTSummaryStruct { timestamp: NOW + DRIFT + 1, ..summary() };
+        assert_eq!(validate(&future).unwrap_err(), SupplyChainError::FutureTimestamp.into());
+    }
+}
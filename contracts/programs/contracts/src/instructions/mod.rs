@@ -1,15 +1,37 @@
 mod config;
 mod user;
+mod role_transition;
+mod product;
 mod batch;
+mod identifier;
 mod handover;
-mod iotSummary;
+mod custody;
+mod route;
+mod shipment;
+mod expiry;
+mod iot_summary;
 mod compliance;
 mod certification;
+mod flag;
+mod dispute;
+mod closure;
+mod claim;
 
 pub use config::*;
 pub use user::*;
+pub use role_transition::*;
+pub use product::*;
 pub use batch::*;
+pub use identifier::*;
 pub use handover::*;
-pub use iotSummary::*;
+pub use custody::*;
+pub use route::*;
+pub use shipment::*;
+pub use expiry::*;
+pub use iot_summary::*;
 pub use compliance::*;
 pub use certification::*;
+pub use flag::*;
+pub use dispute::*;
+pub use closure::*;
+pub use claim::*;
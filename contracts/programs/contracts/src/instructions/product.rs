@@ -0,0 +1,128 @@
+use anchor_lang::prelude::*;
+use crate::state::{Product, ProductDetails, SystemConfig, MAX_DEGRADATION_BANDS, MAX_TEMP_CENTI_C, MIN_TEMP_CENTI_C, PRODUCT_CATEGORY_LENGTH, PRODUCT_NAME_LENGTH, SKU_LENGTH};
+use crate::errors::{CustomError, SupplyChainError};
+use crate::events::ProductUpdated;
+use crate::policy::validate_threshold;
+
+// Products are keyed by SKU alone, so registration is admin-only; otherwise
+// any producer could claim another brand's SKU first.
+#[derive(Accounts)]
+#[instruction(sku: String)]
+pub struct RegisterProduct<'info>{
+    #[account(
+        init,
+        payer=admin,
+        space=8+Product::INIT_SPACE,
+        seeds=[b"product",sku.as_bytes()],
+        bump
+    )]
+    pub product:Account<'info,Product>,
+    #[account(mut)]
+    pub admin:Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config:Account<'info,SystemConfig>,
+    pub system_program:Program<'info,System>
+}
+
+#[derive(Accounts)]
+pub struct UpdateProduct<'info>{
+    #[account(
+        mut,
+        seeds=[b"product",product.sku.as_bytes()],
+        bump=product.bump
+    )]
+    pub product:Account<'info,Product>,
+    pub admin:Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config:Account<'info,SystemConfig>
+}
+
+pub fn _register_product(
+    ctx:Context<RegisterProduct>,
+    sku:String,
+    details:ProductDetails
+)->Result<()>{
+    let admin=ctx.accounts.admin.key();
+    require!(
+        admin == ctx.accounts.system_config.admin_wallet,
+        CustomError::Unauthorized
+    );
+    require!(
+        valid_sku(&sku),
+        SupplyChainError::InvalidProduct
+    );
+
+    let product=&mut ctx.accounts.product;
+    product.sku=sku;
+    product.bump=ctx.bumps.product;
+    _set_product_details(product, details, admin)
+}
+
+// Only affects batches created afterwards; existing batches keep the
+// thresholds and shelf-life model they were created with.
+pub fn _update_product(
+    ctx:Context<UpdateProduct>,
+    details:ProductDetails
+)->Result<()>{
+    let admin=ctx.accounts.admin.key();
+    require!(
+        admin == ctx.accounts.system_config.admin_wallet,
+        CustomError::Unauthorized
+    );
+    _set_product_details(&mut ctx.accounts.product, details, admin)
+}
+
+/// SKUs are canonical (upper-case ASCII letters, digits and dashes) so the
+/// same product cannot be registered twice under differently cased names.
+pub fn valid_sku(sku:&str)->bool{
+    !sku.is_empty()
+        && sku.len() <= SKU_LENGTH
+        && sku.bytes().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'-')
+}
+
+fn _set_product_details(
+    product:&mut Account<Product>,
+    details:ProductDetails,
+    updated_by:Pubkey
+)->Result<()>{
+    require!(
+        !details.name.is_empty() && details.name.len() <= PRODUCT_NAME_LENGTH
+            && !details.category.is_empty() && details.category.len() <= PRODUCT_CATEGORY_LENGTH,
+        SupplyChainError::InvalidProduct
+    );
+    validate_threshold(&details.default_threshold).map_err(|_| error!(SupplyChainError::InvalidProduct))?;
+    // A zero shelf life means batches simply follow their declared expiry date.
+    require!(
+        details.shelf_life_seconds >= 0,
+        SupplyChainError::InvalidProduct
+    );
+    require!(
+        details.degradation.len() <= MAX_DEGRADATION_BANDS
+            && details.degradation.windows(2).all(|pair| pair[0].max_temp_centi_c < pair[1].max_temp_centi_c)
+            && details.degradation.iter().all(|band| {
+                (MIN_TEMP_CENTI_C..=MAX_TEMP_CENTI_C).contains(&band.max_temp_centi_c) && band.rate_bps > 0
+            }),
+        SupplyChainError::InvalidProduct
+    );
+
+    product.name=details.name;
+    product.category=details.category;
+    product.brand_owner=details.brand_owner;
+    product.default_threshold=details.default_threshold;
+    product.shelf_life_seconds=details.shelf_life_seconds;
+    product.degradation=details.degradation;
+
+    emit!(ProductUpdated{
+        product:product.key(),
+        sku:product.sku.clone(),
+        updated_by,
+        timestamp:Clock::get()?.unix_timestamp
+    });
+    Ok(())
+}
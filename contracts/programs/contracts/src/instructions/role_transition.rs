@@ -0,0 +1,99 @@
+use anchor_lang::prelude::*;
+use crate::state::{Role, RoleTransitions, SystemConfig, MAX_ROLES};
+use crate::errors::{CustomError, SupplyChainError};
+use crate::events::RoleTransitionsUpdated;
+
+#[derive(Accounts)]
+pub struct InitializeRoleTransitions<'info>{
+    #[account(init,payer=admin,space=8+RoleTransitions::INIT_SPACE,seeds=[b"role_transitions"],bump)]
+    pub role_transitions:Account<'info,RoleTransitions>,
+    #[account(mut)]
+    pub admin:Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config:Account<'info,SystemConfig>,
+    pub system_program:Program<'info,System>
+}
+
+#[derive(Accounts)]
+pub struct SetRoleTransitions<'info>{
+    #[account(
+        mut,
+        seeds = [b"role_transitions"],
+        bump = role_transitions.bump
+    )]
+    pub role_transitions:Account<'info,RoleTransitions>,
+    pub admin:Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config:Account<'info,SystemConfig>
+}
+
+// Seeds the matrix with the default supply chain:
+// Producer -> Processor/Distributor/Retailer, Processor -> Distributor/Retailer,
+// Distributor -> Retailer, with carriers and warehouses able to sit between
+// any two of those steps.
+pub fn _initialize_role_transitions(ctx:Context<InitializeRoleTransitions>)->Result<()>{
+    require!(
+        ctx.accounts.admin.key() == ctx.accounts.system_config.admin_wallet,
+        CustomError::Unauthorized
+    );
+
+    let logistics=role_mask(&[Role::Transporter, Role::Warehouse]);
+    let transitions=&mut ctx.accounts.role_transitions;
+    transitions.allowed=[0u16;MAX_ROLES];
+    transitions.allowed[Role::Producer as usize]=role_mask(&[Role::Processor, Role::Distributor, Role::Retailer]) | logistics;
+    transitions.allowed[Role::Processor as usize]=role_mask(&[Role::Distributor, Role::Retailer]) | logistics;
+    transitions.allowed[Role::Distributor as usize]=role_mask(&[Role::Retailer]) | logistics;
+    transitions.allowed[Role::Transporter as usize]=role_mask(&[Role::Processor, Role::Distributor, Role::Retailer]) | logistics;
+    transitions.allowed[Role::Warehouse as usize]=role_mask(&[Role::Processor, Role::Distributor, Role::Retailer]) | logistics;
+    transitions.bump=ctx.bumps.role_transitions;
+    Ok(())
+}
+
+// Replaces the set of roles `from_role` may hand a batch to. An empty list
+// stops that role from handing batches on at all.
+pub fn _set_role_transitions(
+    ctx:Context<SetRoleTransitions>,
+    from_role:Role,
+    to_roles:Vec<Role>
+)->Result<()>{
+    require!(
+        ctx.accounts.admin.key() == ctx.accounts.system_config.admin_wallet,
+        CustomError::Unauthorized
+    );
+    require!(
+        is_custodian_role(&from_role) && to_roles.iter().all(is_custodian_role),
+        CustomError::InvalidRole
+    );
+
+    let allowed=role_mask(&to_roles);
+    ctx.accounts.role_transitions.allowed[from_role.clone() as usize]=allowed;
+
+    emit!(RoleTransitionsUpdated{
+        from_role,
+        allowed
+    });
+    Ok(())
+}
+
+pub fn validate_role_transition(transitions:&RoleTransitions, from_role:&Role, to_role:&Role)->Result<()>{
+    require!(
+        transitions.allowed[from_role.clone() as usize] & role_mask(std::slice::from_ref(to_role)) != 0,
+        SupplyChainError::InvalidRoleTransition
+    );
+    Ok(())
+}
+
+fn role_mask(roles:&[Role])->u16{
+    roles.iter().fold(0,|mask,role| mask | 1 << (role.clone() as u16))
+}
+
+// Only roles that can hold a batch take part in handovers.
+pub fn is_custodian_role(role:&Role)->bool{
+    !matches!(role, Role::None | Role::Regulator | Role::Administrator)
+}
@@ -0,0 +1,127 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::SupplyChainError, events::RouteDeviated, geo::{valid_coordinates, waypoint_of, within_route}, history::append_event, state::{Batch, BatchStatus, Event, EventType, GeoPoint, Geofence, PolicyConfig, RouteCorridor, RoutePlan, Waypoint, MAX_ROUTE_WAYPOINTS}};
+
+#[derive(Accounts)]
+pub struct DefineRoute<'info> {
+    #[account(
+        init,
+        payer = producer,
+        space = 8 + RouteCorridor::INIT_SPACE,
+        seeds = [b"route", batch.key().as_ref()],
+        bump
+    )]
+    pub route: Account<'info, RouteCorridor>,
+
+    #[account(
+        mut,
+        has_one = producer,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(mut)]
+    pub producer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn _define_route(
+    ctx: Context<DefineRoute>,
+    waypoints: Vec<Waypoint>,
+    corridor_width_m: u32,
+    destination: Option<Geofence>,
+) -> Result<()> {
+    require!(
+        ctx.accounts.batch.status == BatchStatus::Registered,
+        SupplyChainError::RouteAlreadyUnderway
+    );
+
+    _set_route(
+        &mut ctx.accounts.route,
+        &mut ctx.accounts.batch,
+        RoutePlan { waypoints, corridor_width_m, destination },
+        ctx.bumps.route,
+    )
+}
+
+/// Validates a route plan and writes it to the batch's corridor account.
+pub fn _set_route(route: &mut RouteCorridor, batch: &mut Account<Batch>, plan: RoutePlan, bump: u8) -> Result<()> {
+    let RoutePlan { waypoints, corridor_width_m, destination } = plan;
+
+    require!(
+        waypoints.len() <= MAX_ROUTE_WAYPOINTS && (!waypoints.is_empty() || destination.is_some()),
+        SupplyChainError::InvalidRoute
+    );
+    require!(
+        waypoints.is_empty() || corridor_width_m > 0,
+        SupplyChainError::InvalidRoute
+    );
+    require!(
+        waypoints.iter().all(|w| valid_coordinates(w.lat_e7, w.lon_e7)),
+        SupplyChainError::InvalidLocation
+    );
+    if let Some(fence) = &destination {
+        require!(
+            valid_coordinates(fence.lat_e7, fence.lon_e7) && fence.radius_m > 0,
+            SupplyChainError::InvalidLocation
+        );
+    }
+
+    route.batch = batch.key();
+    route.waypoints = waypoints;
+    route.corridor_width_m = corridor_width_m;
+    route.destination = destination;
+    route.bump = bump;
+    batch.route_defined = true;
+
+    Ok(())
+}
+
+/// Checks a reported location against the batch's route, if it has one, and
+/// raises `route_deviation` when it falls outside. Callers pass the route as
+/// an optional account; it must be present once a route has been defined.
+/// Only the first deviation is written to the batch log; later ones are
+/// emitted so the log can't be filled by a batch wandering off route.
+pub fn _check_route(
+    batch: &mut Batch,
+    route: Option<&RouteCorridor>,
+    policy: &PolicyConfig,
+    location: &GeoPoint,
+    reported_by: Pubkey,
+    timestamp: i64,
+) -> Result<()> {
+    if !batch.route_defined {
+        return Ok(());
+    }
+    let route = route.ok_or(SupplyChainError::RouteAccountMissing)?;
+    if within_route(route, location) {
+        return Ok(());
+    }
+
+    if !batch.compliance.route_deviation {
+        batch.compliance.route_deviation = true;
+
+        let deviation_event = Event {
+            event_type: EventType::RouteDeviation,
+            timestamp,
+            from_wallet: reported_by,
+            to_wallet: batch.current_custodian,
+            details_hash: [0u8; 32],
+            details_cid: String::new(),
+            location: Some(waypoint_of(location)),
+        };
+
+        append_event(batch, policy, deviation_event)?;
+    }
+
+    emit!(RouteDeviated {
+        batch_id: batch.id.clone(),
+        custodian: batch.current_custodian,
+        location: location.clone(),
+        timestamp,
+    });
+
+    Ok(())
+}
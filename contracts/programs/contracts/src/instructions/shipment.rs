@@ -0,0 +1,276 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::SupplyChainError, events::{ShipmentCancelled, ShipmentDispatched, ShipmentSettled}, geo::valid_coordinates, history::append_event, instructions::_require_not_expired, state::{Batch, BatchStatus, Event, EventType, Geofence, ShipmentOutcome, ShipmentPlan, SystemConfig, UserProfile, Waypoint}};
+
+#[derive(Accounts)]
+pub struct DispatchShipment<'info> {
+    #[account(
+        init,
+        payer = sender,
+        space = 8 + ShipmentPlan::INIT_SPACE,
+        seeds = [b"shipment", batch.key().as_ref()],
+        bump
+    )]
+    pub shipment_plan: Account<'info, ShipmentPlan>,
+
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(
+        seeds = [b"user", sender.key().as_ref()],
+        bump = sender_profile.bump
+    )]
+    pub sender_profile: Account<'info, UserProfile>,
+
+    #[account(mut)]
+    pub sender: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config: Account<'info, SystemConfig>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// Permissionless: anyone may mark an overdue shipment late.
+#[derive(Accounts)]
+pub struct SettleOverdueShipment<'info> {
+    #[account(
+        mut,
+        seeds = [b"shipment", batch.key().as_ref()],
+        bump = shipment_plan.bump,
+        has_one = batch
+    )]
+    pub shipment_plan: Account<'info, ShipmentPlan>,
+
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+}
+
+// The sender (or the admin) calls off an open plan, e.g. one the crank marked
+// late, so the batch is no longer locked to `expected_receiver`. The plan's
+// rent goes back to the sender.
+#[derive(Accounts)]
+pub struct CancelShipment<'info> {
+    #[account(
+        mut,
+        close = sender,
+        seeds = [b"shipment", batch.key().as_ref()],
+        bump = shipment_plan.bump,
+        has_one = batch,
+        has_one = sender
+    )]
+    pub shipment_plan: Account<'info, ShipmentPlan>,
+
+    #[account(
+        mut,
+        seeds = [b"batch", batch.producer.as_ref(), batch.id.as_bytes()],
+        bump = batch.bump
+    )]
+    pub batch: Account<'info, Batch>,
+
+    #[account(mut)]
+    pub sender: SystemAccount<'info>,
+
+    pub caller: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = system_config.bump
+    )]
+    pub system_config: Account<'info, SystemConfig>,
+}
+
+pub fn _dispatch_shipment(
+    ctx: Context<DispatchShipment>,
+    expected_receiver: Pubkey,
+    origin: Waypoint,
+    destination: Geofence,
+    max_transit_seconds: i64,
+) -> Result<()> {
+    let plan = &mut ctx.accounts.shipment_plan;
+    let batch = &mut ctx.accounts.batch;
+    let sender_profile = &ctx.accounts.sender_profile;
+    let sender = &ctx.accounts.sender;
+    let clock = Clock::get()?;
+
+    require!(
+        sender_profile.is_approved,
+        SupplyChainError::UserNotApproved
+    );
+    require!(
+        sender.key() == batch.current_custodian,
+        SupplyChainError::NotCurrentCustodian
+    );
+    require!(
+        !batch.shipment_open,
+        SupplyChainError::ShipmentAlreadyOpen
+    );
+    require!(
+        !matches!(batch.status, BatchStatus::Flagged | BatchStatus::Recalled | BatchStatus::Closed),
+        SupplyChainError::BatchNotCompliant
+    );
+    _require_not_expired(batch, clock.unix_timestamp)?;
+    require!(
+        expected_receiver != Pubkey::default() && expected_receiver != sender.key(),
+        SupplyChainError::InvalidShipmentPlan
+    );
+    require!(
+        max_transit_seconds > 0,
+        SupplyChainError::InvalidShipmentPlan
+    );
+    require!(
+        valid_coordinates(origin.lat_e7, origin.lon_e7)
+            && valid_coordinates(destination.lat_e7, destination.lon_e7)
+            && destination.radius_m > 0,
+        SupplyChainError::InvalidLocation
+    );
+
+    plan.batch = batch.key();
+    plan.sender = sender.key();
+    plan.expected_receiver = expected_receiver;
+    plan.origin = origin;
+    plan.destination = destination;
+    plan.dispatched_at = clock.unix_timestamp;
+    plan.deadline = clock.unix_timestamp.saturating_add(max_transit_seconds);
+    plan.outcome = None;
+    plan.bump = ctx.bumps.shipment_plan;
+    batch.shipment_open = true;
+
+    let dispatch_event = Event {
+        event_type: EventType::Dispatch,
+        timestamp: clock.unix_timestamp,
+        from_wallet: sender.key(),
+        to_wallet: expected_receiver,
+        details_hash: [0u8; 32],
+        details_cid: String::new(),
+        location: None,
+    };
+
+    append_event(batch, &ctx.accounts.system_config.policy, dispatch_event)?;
+
+    emit!(ShipmentDispatched {
+        batch_id: batch.id.clone(),
+        sender: sender.key(),
+        expected_receiver,
+        deadline: plan.deadline,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+pub fn _settle_overdue_shipment(ctx: Context<SettleOverdueShipment>) -> Result<()> {
+    let plan = &mut ctx.accounts.shipment_plan;
+    let batch = &mut ctx.accounts.batch;
+    let clock = Clock::get()?;
+
+    require!(
+        plan.outcome.is_none() && clock.unix_timestamp > plan.deadline,
+        SupplyChainError::ShipmentNotOverdue
+    );
+
+    plan.outcome = Some(ShipmentOutcome::Late);
+    batch.shipment_outcome = Some(ShipmentOutcome::Late);
+
+    emit!(ShipmentSettled {
+        batch_id: batch.id.clone(),
+        outcome: ShipmentOutcome::Late,
+        deadline: plan.deadline,
+        delivered_at: 0,
+    });
+
+    Ok(())
+}
+
+pub fn _cancel_shipment(ctx: Context<CancelShipment>) -> Result<()> {
+    let plan = &ctx.accounts.shipment_plan;
+    let batch = &mut ctx.accounts.batch;
+    let caller = &ctx.accounts.caller;
+    let system_config = &ctx.accounts.system_config;
+    let clock = Clock::get()?;
+
+    require!(
+        caller.key() == plan.sender || caller.key() == system_config.admin_wallet,
+        SupplyChainError::InvalidRole
+    );
+
+    // A plan the crank already settled as late keeps that outcome.
+    let outcome = plan.outcome.clone().unwrap_or(ShipmentOutcome::Cancelled);
+    batch.shipment_open = false;
+    batch.shipment_outcome = Some(outcome.clone());
+
+    let cancel_event = Event {
+        event_type: EventType::ShipmentCancelled,
+        timestamp: clock.unix_timestamp,
+        from_wallet: caller.key(),
+        to_wallet: plan.expected_receiver,
+        details_hash: [0u8; 32],
+        details_cid: String::new(),
+        location: None,
+    };
+
+    append_event(batch, &system_config.policy, cancel_event)?;
+
+    emit!(ShipmentCancelled {
+        batch_id: batch.id.clone(),
+        cancelled_by: caller.key(),
+        expected_receiver: plan.expected_receiver,
+        outcome,
+        timestamp: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Called on every custody move. Once a plan is open the batch may only go to
+/// the planned receiver; receipt settles the plan (unless the crank already
+/// marked it late) and closes it back to the sender.
+pub fn _receive_shipment<'info>(
+    batch: &mut Batch,
+    plan: Option<&mut Account<'info, ShipmentPlan>>,
+    sender: AccountInfo<'info>,
+    receiver: &Pubkey,
+    now: i64,
+) -> Result<()> {
+    if !batch.shipment_open {
+        return Ok(());
+    }
+    let plan = plan.ok_or(SupplyChainError::ShipmentPlanMissing)?;
+    require!(
+        *receiver == plan.expected_receiver,
+        SupplyChainError::UnexpectedReceiver
+    );
+    require!(
+        sender.key() == plan.sender,
+        SupplyChainError::NotCurrentCustodian
+    );
+
+    let outcome = match &plan.outcome {
+        Some(outcome) => outcome.clone(),
+        None if now <= plan.deadline => ShipmentOutcome::OnTime,
+        None => ShipmentOutcome::Late,
+    };
+
+    batch.shipment_open = false;
+    batch.shipment_outcome = Some(outcome.clone());
+
+    emit!(ShipmentSettled {
+        batch_id: batch.id.clone(),
+        outcome,
+        deadline: plan.deadline,
+        delivered_at: now,
+    });
+
+    plan.close(sender)
+}
@@ -1,4 +1,4 @@
-use anchor_lang::{accounts::{self, account}, prelude::*};
+use anchor_lang::prelude::*;
 use crate::{errors::CustomError, events::{UserEvent}, state::{Role, SystemConfig, UserProfile}};
 #[derive(Accounts)]
 pub struct RegisterUser<'info>{
@@ -18,7 +18,7 @@ pub fn _register_user(
     let zero_hash = [0u8; 32];
     require!(profile_hash != zero_hash, CustomError::InvalidWallet);
 
-    user_profile.user_wallet=*ctx.accounts.user.key;
+    user_profile.user_wallet = *ctx.accounts.user.key;
     user_profile.role = Role::None;
     user_profile.profile_hash=profile_hash;
     user_profile.is_approved=false;
@@ -1,86 +1,300 @@
 #![allow(unexpected_cfgs)]
 use anchor_lang::prelude::*;
-mod state;
+pub mod state;
 mod instructions;
 mod errors;
 mod events;
+pub mod history;
+pub mod policy;
+pub mod geo;
+pub mod gs1;
+pub mod cid;
 use instructions::*;
 use crate::state::Role;
 use crate::state::OriginDetails;
 use crate::state::IoTSummaryStruct;
+use crate::state::ComplianceReport;
+use crate::state::PolicyConfig;
+use crate::state::{GeoPoint, Geofence, ProductDetails, RoutePlan, Waypoint};
 
 declare_id!("5fm9Ah8DmB6mMFv6jqgBVEj4MZbNF5qDP62TwekEbdev");
 
-#[program]
-pub mod contracts {
-    
+// Anchor 0.31's `#[program]` expansion calls the deprecated
+// `AccountInfo::realloc` from its generated IDL handlers, so the allowance is
+// scoped to the module the macro expands into rather than the whole crate.
+#[allow(deprecated)]
+mod anchor_program {
     use super::*;
 
-     pub fn intialize_config(
-     ctx:Context<InitializeConfig>,
-     admin_wallet:Pubkey,
-     oracle_wallet:Pubkey
-     )->Result<()>{
-          _initialze_config(ctx, admin_wallet, oracle_wallet)
-     }
-
-     pub fn register_user(
-          ctx:Context<RegisterUser>,
-          profile_hash:[u8;32],
-     )->Result<()>{
-          _register_user(ctx, profile_hash)
-     }
-
-     pub fn approve_user(
-          ctx:Context<ApproveUser>,
-     role:Role
-     )->Result<()>{
-          _approve_user(ctx, role)
-     }
-
-     pub fn create_batch(
-          ctx: Context<CreateBatch>,
-          batch_id: String,
-          origin_details: OriginDetails,
-          metadata_hash: [u8; 32],
-          metadata_cid: String,
-     )->Result<()>{
-          _create_batch(ctx, batch_id, origin_details, metadata_hash, metadata_cid)
-     }
-
-     pub fn log_handover(
-          ctx: Context<LogHandover>,
-          to_wallet: Pubkey,
-          details_hash: [u8; 32],
-          details_cid: String,
-     )->Result<()>{
-          _log_handover(ctx, to_wallet, details_hash, details_cid)
-     }
-     pub fn flag_batch(
-          ctx: Context<FlagBatch>,
-          reason: String,
-     ) -> Result<()> {
-          _flag_batch(ctx, reason)
-     }
-     pub fn issue_certification(
-          ctx: Context<IssueCertification>,
-          cert_type: String,
-          cert_hash: [u8; 32],
-          cert_cid: String,
-     ) -> Result<()> {
-          _issue_certification(ctx, cert_type, cert_hash, cert_cid)
-     }
-     pub fn check_compliance(ctx: Context<CheckCompliance>) -> Result<()> {
-          _check_compliance(ctx)
-     }
-     pub fn update_iot_summary(
-          ctx: Context<UpdateIotSummary>,
-          summary: IoTSummaryStruct,
-          new_hash: [u8; 32],
-          new_cid: String,
-     ) -> Result<()> {
-          _update_iot_summary(ctx, summary, new_hash, new_cid)
-     }
+    #[program]
+    pub mod contracts {
+    
+        use super::*;
+
+         pub fn intialize_config(
+         ctx:Context<InitializeConfig>,
+         admin_wallet:Pubkey,
+         oracle_wallet:Pubkey
+         )->Result<()>{
+              _initialze_config(ctx, admin_wallet, oracle_wallet)
+         }
+
+         pub fn update_policy(
+              ctx:Context<UpdateConfig>,
+              policy:PolicyConfig
+         )->Result<()>{
+              _update_policy(ctx, policy)
+         }
+
+         pub fn initialize_role_transitions(
+              ctx:Context<InitializeRoleTransitions>
+         )->Result<()>{
+              _initialize_role_transitions(ctx)
+         }
+
+         pub fn set_role_transitions(
+              ctx:Context<SetRoleTransitions>,
+              from_role:Role,
+              to_roles:Vec<Role>
+         )->Result<()>{
+              _set_role_transitions(ctx, from_role, to_roles)
+         }
+
+         pub fn register_product(ctx:Context<RegisterProduct>, sku:String, details:ProductDetails)->Result<()>{
+              _register_product(ctx, sku, details)
+         }
+
+         pub fn update_product(ctx:Context<UpdateProduct>, details:ProductDetails)->Result<()>{
+              _update_product(ctx, details)
+         }
+
+         pub fn register_user(
+              ctx:Context<RegisterUser>,
+              profile_hash:[u8;32],
+         )->Result<()>{
+              _register_user(ctx, profile_hash)
+         }
+
+         pub fn approve_user(
+              ctx:Context<ApproveUser>,
+         role:Role
+         )->Result<()>{
+              _approve_user(ctx, role)
+         }
+
+         pub fn create_batch(
+              ctx: Context<CreateBatch>,
+              batch_id: String,
+              origin_details: OriginDetails,
+              metadata_hash: [u8; 32],
+              metadata_cid: String,
+              metadata_schema_version: u16,
+              route: Option<RoutePlan>,
+         )->Result<()>{
+              _create_batch(ctx, batch_id, origin_details, metadata_hash, metadata_cid, metadata_schema_version, route)
+         }
+
+         pub fn assign_gs1_lot(ctx: Context<AssignGs1Lot>, lot: String)->Result<()>{
+              _assign_gs1_lot(ctx, lot)
+         }
+
+         pub fn register_logistic_unit(ctx: Context<RegisterLogisticUnit>, sscc: String)->Result<()>{
+              _register_logistic_unit(ctx, sscc)
+         }
+
+         pub fn define_route(
+              ctx: Context<DefineRoute>,
+              waypoints: Vec<Waypoint>,
+              corridor_width_m: u32,
+              destination: Option<Geofence>,
+         )->Result<()>{
+              _define_route(ctx, waypoints, corridor_width_m, destination)
+         }
+
+         pub fn dispatch_shipment(
+              ctx: Context<DispatchShipment>,
+              expected_receiver: Pubkey,
+              origin: Waypoint,
+              destination: Geofence,
+              max_transit_seconds: i64,
+         )->Result<()>{
+              _dispatch_shipment(ctx, expected_receiver, origin, destination, max_transit_seconds)
+         }
+
+         pub fn settle_overdue_shipment(ctx: Context<SettleOverdueShipment>)->Result<()>{
+              _settle_overdue_shipment(ctx)
+         }
+
+         pub fn cancel_shipment(ctx: Context<CancelShipment>)->Result<()>{
+              _cancel_shipment(ctx)
+         }
+
+         pub fn log_handover(
+              ctx: Context<LogHandover>,
+              to_wallet: Pubkey,
+              details_hash: [u8; 32],
+              details_cid: String,
+         )->Result<()>{
+              _log_handover(ctx, to_wallet, details_hash, details_cid)
+         }
+         pub fn transfer_custody(
+              ctx: Context<LogHandover>,
+              to_wallet: Pubkey,
+              details_hash: [u8; 32],
+              details_cid: String,
+         )->Result<()>{
+              _transfer_custody(ctx, to_wallet, details_hash, details_cid)
+         }
+         pub fn transfer_ownership(
+              ctx: Context<TransferOwnership>,
+              to_wallet: Pubkey,
+              details_hash: [u8; 32],
+         )->Result<()>{
+              _transfer_ownership(ctx, to_wallet, details_hash)
+         }
+         pub fn bind_service_provider(ctx: Context<BindServiceProvider>) -> Result<()> {
+              _bind_service_provider(ctx)
+         }
+         pub fn unbind_service_provider(ctx: Context<UnbindServiceProvider>) -> Result<()> {
+              _unbind_service_provider(ctx)
+         }
+         pub fn log_checkpoint(
+              ctx: Context<LogCustodyUpdate>,
+              location: GeoPoint,
+              details_hash: [u8; 32],
+              details_cid: String,
+         ) -> Result<()> {
+              _log_checkpoint(ctx, location, details_hash, details_cid)
+         }
+         pub fn log_storage_update(
+              ctx: Context<LogCustodyUpdate>,
+              details_hash: [u8; 32],
+              details_cid: String,
+         ) -> Result<()> {
+              _log_storage_update(ctx, details_hash, details_cid)
+         }
+         pub fn log_processing_update(
+              ctx: Context<LogCustodyUpdate>,
+              details_hash: [u8; 32],
+              details_cid: String,
+         ) -> Result<()> {
+              _log_processing_update(ctx, details_hash, details_cid)
+         }
+         pub fn flag_batch(
+              ctx: Context<FlagBatch>,
+              reason: String,
+         ) -> Result<()> {
+              _flag_batch(ctx, reason)
+         }
+         pub fn issue_certification(
+              ctx: Context<IssueCertification>,
+              cert_type: String,
+              cert_hash: [u8; 32],
+              cert_cid: String,
+         ) -> Result<()> {
+              _issue_certification(ctx, cert_type, cert_hash, cert_cid)
+         }
+         pub fn revoke_certification(
+              ctx: Context<RevokeCertification>,
+              reason_hash: [u8; 32],
+         ) -> Result<()> {
+              _revoke_certification(ctx, reason_hash)
+         }
+         pub fn sync_certification_status<'info>(ctx: Context<'_, '_, 'info, 'info, SyncCertificationStatus<'info>>) -> Result<()> {
+              _sync_certification_status(ctx)
+         }
+         pub fn evaluate_compliance(ctx: Context<EvaluateCompliance>) -> Result<ComplianceReport> {
+              _evaluate_compliance(ctx)
+         }
+         pub fn check_compliance(ctx: Context<CheckCompliance>) -> Result<()> {
+              _check_compliance(ctx)
+         }
+         pub fn update_iot_summary(
+              ctx: Context<UpdateIotSummary>,
+              summary: IoTSummaryStruct,
+              new_hash: [u8; 32],
+              new_cid: String,
+         ) -> Result<()> {
+              _update_iot_summary(ctx, summary, new_hash, new_cid)
+         }
+         pub fn resolve_flag(
+              ctx: Context<ResolveFlag>,
+              report_hash: [u8; 32],
+              report_cid: String,
+         ) -> Result<()> {
+              _resolve_flag(ctx, report_hash, report_cid)
+         }
+         pub fn escalate_flag(
+              ctx: Context<EscalateFlag>,
+              report_hash: [u8; 32],
+              report_cid: String,
+         ) -> Result<()> {
+              _escalate_flag(ctx, report_hash, report_cid)
+         }
+         pub fn open_dispute(
+              ctx: Context<OpenDispute>,
+              iot_hash: [u8; 32],
+              evidence_hash: [u8; 32],
+              evidence_cid: String,
+         ) -> Result<()> {
+              _open_dispute(ctx, iot_hash, evidence_hash, evidence_cid)
+         }
+         pub fn rule_dispute(
+              ctx: Context<RuleDispute>,
+              upheld: bool,
+              ruling_hash: [u8; 32],
+         ) -> Result<()> {
+              _rule_dispute(ctx, upheld, ruling_hash)
+         }
+         pub fn record_sale(
+              ctx: Context<RecordSale>,
+              quantity: u64,
+              sale_hash: [u8; 32],
+         ) -> Result<()> {
+              _record_sale(ctx, quantity, sale_hash)
+         }
+         pub fn mark_consumed(
+              ctx: Context<MarkConsumed>,
+              details_hash: [u8; 32],
+         ) -> Result<()> {
+              _mark_consumed(ctx, details_hash)
+         }
+         pub fn mark_destroyed(
+              ctx: Context<MarkDestroyed>,
+              disposal_hash: [u8; 32],
+              disposal_cid: String,
+         ) -> Result<()> {
+              _mark_destroyed(ctx, disposal_hash, disposal_cid)
+         }
+         pub fn issue_consumer_claim(
+              ctx: Context<IssueConsumerClaim>,
+              code_hash: [u8; 32],
+              quantity: u64,
+         ) -> Result<()> {
+              _issue_consumer_claim(ctx, code_hash, quantity)
+         }
+         pub fn commit_consumer_claim(
+              ctx: Context<CommitConsumerClaim>,
+              commitment_hash: [u8; 32],
+         ) -> Result<()> {
+              _commit_consumer_claim(ctx, commitment_hash)
+         }
+         pub fn redeem_consumer_claim(
+              ctx: Context<RedeemConsumerClaim>,
+              code: [u8; 32],
+         ) -> Result<()> {
+              _redeem_consumer_claim(ctx, code)
+         }
+         pub fn cancel_consumer_claim<'info>(ctx: Context<'_, '_, 'info, 'info, CancelConsumerClaim<'info>>) -> Result<()> {
+              _cancel_consumer_claim(ctx)
+         }
+         pub fn mark_expired(ctx: Context<MarkExpired>) -> Result<()> {
+              _mark_expired(ctx)
+         }
+         pub fn archive_batch<'info>(ctx: Context<'_, '_, 'info, 'info, ArchiveBatch<'info>>) -> Result<()> {
+              _archive_batch(ctx)
+         }
      
+    }
 }
-
+pub use anchor_program::*;
@@ -0,0 +1,90 @@
+use anchor_lang::prelude::*;
+
+use crate::{cid::canonical_cid, errors::CustomError, state::{PolicyConfig, ThresholdStruct, CID_PREFIX_LENGTH, DEFAULT_EXCURSION_SHELF_LIFE_FACTOR, DEFAULT_IOT_STALENESS_SECONDS, DEFAULT_MAX_BREACH_DURATION, DEFAULT_MAX_CLOCK_DRIFT_SECONDS, DEFAULT_MAX_HANDOVERS, DEFAULT_MAX_HUMIDITY_BPS, DEFAULT_MAX_TEMP_CENTI_C, DEFAULT_SEVERE_KEYWORDS, DEFAULT_COLD_CHAIN_KEYWORDS, DEFAULT_FRAUD_KEYWORDS, DEFAULT_BREACH_KEYWORDS, CLOSING_EVENT_SLOTS, EVENT_LENGTH, MAX_CID_PREFIXES, MAX_HUMIDITY_BPS, MAX_POLICY_KEYWORDS, MAX_TEMP_CENTI_C, MIN_TEMP_CENTI_C, POLICY_KEYWORD_LENGTH}};
+
+pub fn default_policy() -> PolicyConfig {
+    PolicyConfig {
+        version: 1,
+        iot_staleness_seconds: DEFAULT_IOT_STALENESS_SECONDS,
+        max_clock_drift_seconds: DEFAULT_MAX_CLOCK_DRIFT_SECONDS,
+        default_threshold: ThresholdStruct {
+            max_temp_centi_c: DEFAULT_MAX_TEMP_CENTI_C,
+            max_humidity_bps: DEFAULT_MAX_HUMIDITY_BPS,
+            max_breach_duration: DEFAULT_MAX_BREACH_DURATION,
+            excursion_shelf_life_factor: DEFAULT_EXCURSION_SHELF_LIFE_FACTOR,
+        },
+        max_handovers: DEFAULT_MAX_HANDOVERS,
+        max_events: (EVENT_LENGTH - CLOSING_EVENT_SLOTS) as u16,
+        allowed_cid_prefixes: Vec::new(),
+        severe_keywords: DEFAULT_SEVERE_KEYWORDS.iter().map(|k| k.to_string()).collect(),
+        cold_chain_keywords: DEFAULT_COLD_CHAIN_KEYWORDS.iter().map(|k| k.to_string()).collect(),
+        fraud_keywords: DEFAULT_FRAUD_KEYWORDS.iter().map(|k| k.to_string()).collect(),
+        breach_keywords: DEFAULT_BREACH_KEYWORDS.iter().map(|k| k.to_string()).collect(),
+    }
+}
+
+pub fn validate_policy(policy: &PolicyConfig) -> Result<()> {
+    require!(
+        policy.iot_staleness_seconds > 0 && policy.max_clock_drift_seconds >= 0,
+        CustomError::InvalidPolicy
+    );
+    require!(
+        policy.max_handovers > 0,
+        CustomError::InvalidPolicy
+    );
+    require!(
+        policy.max_events > 0 && policy.max_events as usize <= EVENT_LENGTH - CLOSING_EVENT_SLOTS,
+        CustomError::InvalidPolicy
+    );
+    validate_threshold(&policy.default_threshold)?;
+    require!(
+        policy.allowed_cid_prefixes.len() <= MAX_CID_PREFIXES
+            && policy.allowed_cid_prefixes.iter().all(|p| !p.is_empty() && p.len() <= CID_PREFIX_LENGTH),
+        CustomError::InvalidPolicy
+    );
+    require!(
+        [&policy.severe_keywords, &policy.cold_chain_keywords, &policy.fraud_keywords, &policy.breach_keywords]
+            .iter()
+            .all(|keywords| valid_keywords(keywords)),
+        CustomError::InvalidPolicy
+    );
+    Ok(())
+}
+
+fn valid_keywords(keywords: &[String]) -> bool {
+    keywords.len() <= MAX_POLICY_KEYWORDS
+        && keywords.iter().all(|k| {
+            !k.is_empty() && k.len() <= POLICY_KEYWORD_LENGTH && *k == k.to_lowercase()
+        })
+}
+
+pub fn validate_threshold(threshold: &ThresholdStruct) -> Result<()> {
+    require!(
+        (MIN_TEMP_CENTI_C..=MAX_TEMP_CENTI_C).contains(&threshold.max_temp_centi_c)
+            && threshold.max_humidity_bps <= MAX_HUMIDITY_BPS,
+        CustomError::InvalidPolicy
+    );
+    Ok(())
+}
+
+/// A CID is accepted when it is a well-formed CIDv0/CIDv1 whose canonical
+/// form fits in `max_len` and starts with one of the allowed prefixes. An
+/// empty prefix list allows any CID. Returns the canonical form to store.
+pub fn allowed_cid(policy: &PolicyConfig, cid: &str, max_len: usize) -> Option<String> {
+    canonical_cid(cid).filter(|cid| {
+        cid.len() <= max_len
+            && (policy.allowed_cid_prefixes.is_empty()
+                || policy.allowed_cid_prefixes.iter().any(|p| cid.starts_with(p.as_str())))
+    })
+}
+
+pub fn is_severe_reason(policy: &PolicyConfig, reason: &str) -> bool {
+    reason_matches(&policy.severe_keywords, reason)
+}
+
+/// Case-insensitive check of a flag reason against one of the policy's
+/// keyword lists.
+pub fn reason_matches(keywords: &[String], reason: &str) -> bool {
+    let reason = reason.to_lowercase();
+    keywords.iter().any(|k| reason.contains(k.as_str()))
+}
@@ -1,15 +1,62 @@
-use anchor_lang::prelude::{borsh::{BorshDeserialize, BorshSerialize}, *};
+use anchor_lang::prelude::*;
 
 
 pub const BATCH_ID_LENGTH:usize=64;
 pub const METADATA_CID_LENGTH:usize=128;
 pub const EVENT_LENGTH:usize=50;
-pub const PRODUCT_TYPE_LENGTH:usize=64;
+// Log slots held back for the event that closes a batch, so a batch whose log
+// is otherwise full can still be closed and archived.
+pub const CLOSING_EVENT_SLOTS:usize=1;
+pub const SKU_LENGTH:usize=32;
+pub const PRODUCT_NAME_LENGTH:usize=64;
+pub const PRODUCT_CATEGORY_LENGTH:usize=32;
+pub const GTIN_LENGTH:usize=14;
+pub const LOT_LENGTH:usize=20;
+pub const SSCC_LENGTH:usize=18;
+// Shorter than the other CID limits because event CIDs are stored inline in
+// every Batch. CIDv1s are stored in base32, so this fits CIDv0 (46) and any
+// sha2-256 CIDv1 (59 for one-byte codecs) whatever multibase it arrived in.
 pub const DETAILS_CID_LENGTH:usize=64;
 pub const LOCATION_SUMMARY_LENGTH:usize=256;
 pub const CERTIFICATION_TYPE_LENGTH:usize=128;
 pub const CERTIFICATION_CID_LENGTH:usize=128;
-pub const IoT_CID_LENGTH:usize=128;
+pub const IOT_CID_LENGTH:usize=128;
+pub const IOT_HISTORY_LENGTH:usize=24;
+
+// Sensor readings are stored as scaled integers so comparisons are exact and
+// NaN cannot reach on-chain state: temperatures in hundredths of a degree
+// Celsius, relative humidity in basis points (10_000 = 100%), weight in grams.
+pub const TEMP_SCALE:i32=100;
+pub const MIN_TEMP_CENTI_C:i32=-100*TEMP_SCALE;
+pub const MAX_TEMP_CENTI_C:i32=150*TEMP_SCALE;
+pub const MAX_HUMIDITY_BPS:u16=10_000;
+
+pub const MAX_CID_PREFIXES:usize=8;
+pub const CID_PREFIX_LENGTH:usize=16;
+pub const MAX_POLICY_KEYWORDS:usize=8;
+pub const POLICY_KEYWORD_LENGTH:usize=32;
+pub const MAX_ROLES:usize=16;
+pub const MAX_ROUTE_WAYPOINTS:usize=16;
+pub const MAX_DEGRADATION_BANDS:usize=8;
+pub const MAX_BATCH_ID_ENTRIES:usize=16;
+// `DegradationBand.rate_bps` at which shelf life runs at its nominal pace.
+pub const NOMINAL_DEGRADATION_RATE_BPS:u32=10_000;
+// Fixes less precise than this are rejected rather than judged against a route.
+pub const MAX_GEO_ACCURACY_M:u32=5_000;
+
+// Defaults written into the policy at `intialize_config`.
+pub const DEFAULT_IOT_STALENESS_SECONDS:i64=3600;
+pub const DEFAULT_MAX_CLOCK_DRIFT_SECONDS:i64=300;
+pub const DEFAULT_MAX_TEMP_CENTI_C:i32=8*TEMP_SCALE;
+pub const DEFAULT_MAX_HUMIDITY_BPS:u16=9_000;
+pub const DEFAULT_MAX_BREACH_DURATION:u32=1800;
+pub const DEFAULT_MAX_HANDOVERS:u16=20;
+// 0 leaves excursions charged at the temperature band's rate only.
+pub const DEFAULT_EXCURSION_SHELF_LIFE_FACTOR:u16=0;
+pub const DEFAULT_SEVERE_KEYWORDS:[&str;3]=["severe","critical","recall"];
+pub const DEFAULT_COLD_CHAIN_KEYWORDS:[&str;2]=["temperature","cold"];
+pub const DEFAULT_FRAUD_KEYWORDS:[&str;1]=["fraud"];
+pub const DEFAULT_BREACH_KEYWORDS:[&str;2]=["breach","temperature"];
 
 
 #[account]
@@ -19,6 +66,40 @@ pub struct SystemConfig {
     pub admin_wallet: Pubkey, 
     pub oracle_wallet: Pubkey, 
     pub bump: u8, 
+    pub policy: PolicyConfig,
+}
+
+// Admin-editable limits read by the instructions instead of hard-coded
+// values. `version` is bumped on every update.
+#[derive(Clone,AnchorSerialize, AnchorDeserialize, PartialEq, Debug,InitSpace)]
+pub struct PolicyConfig{
+    pub version:u32,
+    pub iot_staleness_seconds:i64,
+    pub max_clock_drift_seconds:i64,
+    pub default_threshold:ThresholdStruct,
+    pub max_handovers:u16,
+    pub max_events:u16,
+    #[max_len(MAX_CID_PREFIXES, CID_PREFIX_LENGTH)]
+    pub allowed_cid_prefixes:Vec<String>,
+    // Lowercase substrings matched against `flag_batch` reasons: severe ones
+    // recall the batch, the others set the matching compliance flags.
+    #[max_len(MAX_POLICY_KEYWORDS, POLICY_KEYWORD_LENGTH)]
+    pub severe_keywords:Vec<String>,
+    #[max_len(MAX_POLICY_KEYWORDS, POLICY_KEYWORD_LENGTH)]
+    pub cold_chain_keywords:Vec<String>,
+    #[max_len(MAX_POLICY_KEYWORDS, POLICY_KEYWORD_LENGTH)]
+    pub fraud_keywords:Vec<String>,
+    #[max_len(MAX_POLICY_KEYWORDS, POLICY_KEYWORD_LENGTH)]
+    pub breach_keywords:Vec<String>
+}
+
+// allowed[from] is a bitmask of the roles a `from` custodian may hand a batch
+// to, both indexed by the `Role` discriminant.
+#[account]
+#[derive(InitSpace)]
+pub struct RoleTransitions{
+    pub allowed:[u16;MAX_ROLES],
+    pub bump:u8
 }
 
 #[account]
@@ -40,36 +121,201 @@ pub struct Batch {
     pub id: String,             
     pub producer: Pubkey,       
     pub current_owner: Pubkey,  
+    // Who physically holds the batch; differs from `current_owner` while a
+    // carrier or warehouse holds it on the owner's behalf.
+    pub current_custodian: Pubkey,
     pub status: BatchStatus,    
+    pub pre_flag_status: BatchStatus,
     pub origin_details: OriginDetails, 
     pub metadata_hash: [u8; 32], 
      #[max_len(METADATA_CID_LENGTH)]
     pub metadata_cid: String, 
+    // Version of the off-chain metadata JSON schema, so clients know how to decode it.
+    pub metadata_schema_version: u16,
     #[max_len(EVENT_LENGTH)]   
     pub events: Vec<Event>, 
+    pub history_root: [u8; 32],
+    pub current_leg: u16,
+    // Full handovers only; custody transfers open legs without counting
+    // against `policy.max_handovers`.
+    pub handover_count: u16,
 
     pub iot_summary:IoTSummaryStruct,
     pub iot_hash:[u8;32],
-    #[max_len(IoT_CID_LENGTH)]
+    #[max_len(IOT_CID_LENGTH)]
     pub iot_cid:String,
-    pub threshold:thresholdStruct,
-    pub compliance:ComplianceFlagsStruct
+    pub threshold:ThresholdStruct,
+    pub compliance:ComplianceFlagsStruct,
+    pub remaining_quantity:u64,
+    // Units promised to outstanding consumer claims; not available for
+    // direct sale until the claims are redeemed or cancelled.
+    pub reserved_quantity:u64,
+    pub closure_reason:Option<ClosureReason>,
+    pub certification_count:u16,
+    pub route_defined:bool,
+    pub shipment_open:bool,
+    // Outcome of the most recent planned shipment.
+    pub shipment_outcome:Option<ShipmentOutcome>,
+    // `origin_details.expiry_date` brought forward by temperature excursions.
+    pub effective_expiry:i64,
+    pub remaining_shelf_life_seconds:i64,
+    pub shelf_life_updated_at:i64,
+    // Snapshot of the product's degradation table taken at creation.
+    #[max_len(MAX_DEGRADATION_BANDS)]
+    pub degradation:Vec<DegradationBand>,
+    // GS1 AI (10) lot number; empty until assigned.
+    #[max_len(LOT_LENGTH)]
+    pub gs1_lot:String,
+    pub bump:u8
 }
 
 #[account]
 #[derive(InitSpace)]
 pub struct IoTSummaryStruct{
     pub timestamp:i64,
-    pub min_temp:f32,
-    pub max_temp:f32,
-    pub avg_temp:f32,
-    pub min_humidity:f32,
-    pub max_humidity:f32,
-    pub avg_humidity:f32,
+    pub min_temp_centi_c:i32,
+    pub max_temp_centi_c:i32,
+    pub avg_temp_centi_c:i32,
+    pub min_humidity_bps:u16,
+    pub max_humidity_bps:u16,
+    pub avg_humidity_bps:u16,
     #[max_len(LOCATION_SUMMARY_LENGTH)]
     pub location_summary:String,
     pub breach_detected:bool,
-    pub breach_count:u32
+    pub breach_count:u32,
+    pub location:Option<GeoPoint>
+}
+
+// Coordinates are degrees scaled by 1e7 (about 1 cm at the equator).
+#[derive(Clone,AnchorSerialize, AnchorDeserialize, PartialEq, Debug,InitSpace)]
+pub struct GeoPoint{
+    pub lat_e7:i32,
+    pub lon_e7:i32,
+    pub accuracy_m:u32,
+    pub timestamp:i64
+}
+
+#[derive(Clone,AnchorSerialize, AnchorDeserialize, PartialEq, Debug,InitSpace)]
+pub struct Waypoint{
+    pub lat_e7:i32,
+    pub lon_e7:i32
+}
+
+#[derive(Clone,AnchorSerialize, AnchorDeserialize, PartialEq, Debug,InitSpace)]
+pub struct Geofence{
+    pub lat_e7:i32,
+    pub lon_e7:i32,
+    pub radius_m:u32
+}
+
+// Where a batch is allowed to be: within `corridor_width_m` of the waypoint
+// polyline, or inside the destination geofence. Set at `create_batch`, or by
+// `define_route` while the batch is still `Registered`.
+#[account]
+#[derive(InitSpace)]
+pub struct RouteCorridor{
+    pub batch:Pubkey,
+    #[max_len(MAX_ROUTE_WAYPOINTS)]
+    pub waypoints:Vec<Waypoint>,
+    pub corridor_width_m:u32,
+    pub destination:Option<Geofence>,
+    pub bump:u8
+}
+
+// Route fields passed to `create_batch`; same rules as `define_route`.
+#[derive(Clone,AnchorSerialize, AnchorDeserialize, PartialEq, Debug)]
+pub struct RoutePlan{
+    pub waypoints:Vec<Waypoint>,
+    pub corridor_width_m:u32,
+    pub destination:Option<Geofence>
+}
+
+// Lookup from a human batch id to every live batch that uses it, seeded
+// [b"batch_id", id]. Batch PDAs are namespaced by producer, so several
+// producers may share an id: `create_batch` appends an entry and
+// `archive_batch` removes it, closing the index with its last entry.
+#[account]
+#[derive(InitSpace)]
+pub struct BatchIdIndex{
+    #[max_len(BATCH_ID_LENGTH)]
+    pub id:String,
+    #[max_len(MAX_BATCH_ID_ENTRIES)]
+    pub entries:Vec<BatchIdEntry>,
+    pub bump:u8
+}
+
+#[derive(Clone,AnchorSerialize, AnchorDeserialize, PartialEq, Debug,InitSpace)]
+pub struct BatchIdEntry{
+    pub producer:Pubkey,
+    pub batch:Pubkey
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct BatchArchive{
+    #[max_len(BATCH_ID_LENGTH)]
+    pub id:String,
+    pub producer:Pubkey,
+    pub final_status:BatchStatus,
+    pub closure_reason:Option<ClosureReason>,
+    pub event_count:u32,
+    pub events_root:[u8;32],
+    pub history_root:[u8;32],
+    pub compliance:ComplianceFlagsStruct,
+    pub archived_at:i64,
+    pub bump:u8
+}
+
+// Ring buffer of the most recent IoT summaries for a batch. Entries are
+// appended until the buffer is full, after which `head` points at the oldest
+// entry, which is the next one overwritten.
+#[account]
+#[derive(InitSpace)]
+pub struct IoTHistory{
+    pub batch:Pubkey,
+    pub head:u8,
+    #[max_len(IOT_HISTORY_LENGTH)]
+    pub entries:Vec<IoTHistoryEntry>,
+    pub bump:u8
+}
+
+#[derive(Clone,AnchorSerialize, AnchorDeserialize, PartialEq, Debug,InitSpace)]
+pub struct IoTHistoryEntry{
+    pub timestamp:i64,
+    pub iot_hash:[u8;32],
+    #[max_len(IOT_CID_LENGTH)]
+    pub iot_cid:String,
+    pub min_temp_centi_c:i32,
+    pub max_temp_centi_c:i32,
+    pub avg_temp_centi_c:i32,
+    pub min_humidity_bps:u16,
+    pub max_humidity_bps:u16,
+    pub avg_humidity_bps:u16,
+    pub breach_detected:bool,
+    // Set when a dispute over this summary's breach is upheld.
+    pub breach_overturned:bool,
+    pub custodian:Pubkey,
+    pub leg_index:u16
+}
+
+// Per-custodian aggregate for one leg of a batch's journey. Leg 0 starts at
+// creation with the producer; every handover closes the current leg and opens
+// the next one for the receiver.
+#[account]
+#[derive(InitSpace)]
+pub struct LegSummary{
+    pub batch:Pubkey,
+    pub leg_index:u16,
+    pub custodian:Pubkey,
+    pub started_at:i64,
+    pub ended_at:i64,
+    pub summary_count:u32,
+    pub excursion_count:u32,
+    pub excursion_seconds:u64,
+    // Whoever funded the account: the producer for leg 0, otherwise the
+    // custodian who handed the batch over. Archiving refunds the rent here.
+    pub payer:Pubkey,
+    pub bump:u8
 }
 
 // use this one after prototype , for now just keep it
@@ -89,28 +335,194 @@ pub struct Certification{
     pub bump:u8
 }
 
+#[account]
+#[derive(InitSpace)]
+pub struct Dispute{
+    pub batch:Pubkey,
+    pub opened_by:Pubkey,
+    pub regulator:Pubkey,
+    pub iot_hash:[u8;32],
+    pub summary_timestamp:i64,
+    pub evidence_hash:[u8;32],
+    #[max_len(DETAILS_CID_LENGTH)]
+    pub evidence_cid:String,
+    pub status:DisputeStatus,
+    pub ruling_hash:[u8;32],
+    pub opened_at:i64,
+    pub resolved_at:i64,
+    pub bump:u8
+}
+
+// One-time claim a retailer hands to an end customer with the product. The
+// customer's wallet reveals the code to take custody of `quantity` units; the
+// redeemed claim stays on chain as their proof of purchase.
+#[account]
+#[derive(InitSpace)]
+pub struct ConsumerClaim{
+    pub batch:Pubkey,
+    pub retailer:Pubkey,
+    pub code_hash:[u8;32],
+    pub quantity:u64,
+    pub issued_at:i64,
+    pub redeemed_by:Option<Pubkey>,
+    pub redeemed_at:i64,
+    pub bump:u8
+}
+
+// First half of redeeming a claim: the consumer commits to
+// `sha256(code || consumer)` before revealing the code, so a copied reveal
+// can't be redeemed by another wallet in the same slot.
+#[account]
+#[derive(InitSpace)]
+pub struct ClaimCommitment{
+    pub claim:Pubkey,
+    pub consumer:Pubkey,
+    pub commitment:[u8;32],
+    pub committed_slot:u64,
+    pub bump:u8
+}
+
+// Lets a carrier or warehouse working for the current custodian log
+// checkpoints and storage/processing updates without taking ownership. Only
+// honoured while `custodian` still holds the batch.
+#[account]
+#[derive(InitSpace)]
+pub struct ServiceBinding{
+    pub batch:Pubkey,
+    pub custodian:Pubkey,
+    pub provider:Pubkey,
+    pub bound_at:i64,
+    pub bump:u8
+}
+
+// The sender's plan for the batch's next handover. While it is open the batch
+// can only be handed to `expected_receiver`; receipt (or the overdue crank)
+// settles it against `deadline`, and the sender or admin may cancel it.
+#[account]
+#[derive(InitSpace)]
+pub struct ShipmentPlan{
+    pub batch:Pubkey,
+    pub sender:Pubkey,
+    pub expected_receiver:Pubkey,
+    pub origin:Waypoint,
+    pub destination:Geofence,
+    pub dispatched_at:i64,
+    pub deadline:i64,
+    pub outcome:Option<ShipmentOutcome>,
+    pub bump:u8
+}
+
+// Resolves a scanned GS1 (01) GTIN + (10) lot pair to its batch. Seeds are
+// [b"lot", GTIN-14, lot] so clients can derive the address straight from the
+// DataMatrix contents.
+#[account]
+#[derive(InitSpace)]
+pub struct LotLookup{
+    #[max_len(GTIN_LENGTH)]
+    pub gtin:String,
+    #[max_len(LOT_LENGTH)]
+    pub lot:String,
+    pub batch:Pubkey,
+    pub bump:u8
+}
+
+// A pallet or case labelled with an SSCC, seeded [b"sscc", sscc].
+#[account]
+#[derive(InitSpace)]
+pub struct LogisticUnit{
+    #[max_len(SSCC_LENGTH)]
+    pub sscc:String,
+    pub batch:Pubkey,
+    pub registered_by:Pubkey,
+    pub registered_at:i64,
+    pub bump:u8
+}
+
+// Catalog entry batches are created against. Besides identifying the product
+// it carries the defaults every new batch starts from: the IoT thresholds and
+// a shelf-life model. Degradation bands are ordered by temperature; storage at
+// or below `max_temp_centi_c` uses up shelf life at `rate_bps` /
+// NOMINAL_DEGRADATION_RATE_BPS times wall-clock speed (Q10-style steps).
+#[account]
+#[derive(InitSpace)]
+pub struct Product{
+    #[max_len(SKU_LENGTH)]
+    pub sku:String,
+    #[max_len(PRODUCT_NAME_LENGTH)]
+    pub name:String,
+    #[max_len(PRODUCT_CATEGORY_LENGTH)]
+    pub category:String,
+    // Producer that owns the brand; the only one who may assign GS1 lots
+    // under the product's GTIN.
+    pub brand_owner:Pubkey,
+    pub default_threshold:ThresholdStruct,
+    pub shelf_life_seconds:i64,
+    #[max_len(MAX_DEGRADATION_BANDS)]
+    pub degradation:Vec<DegradationBand>,
+    pub bump:u8
+}
+
+#[derive(Clone,AnchorSerialize, AnchorDeserialize, PartialEq, Debug)]
+pub struct ProductDetails{
+    pub name:String,
+    pub category:String,
+    pub brand_owner:Pubkey,
+    pub default_threshold:ThresholdStruct,
+    pub shelf_life_seconds:i64,
+    pub degradation:Vec<DegradationBand>
+}
+
 #[derive(Clone,AnchorSerialize, AnchorDeserialize, PartialEq, Debug,InitSpace)]
-pub struct thresholdStruct{
-    pub max_temp : f32,
-    pub max_humidity:f32,
-    pub max_breach_duration : u32
+pub struct DegradationBand{
+    pub max_temp_centi_c:i32,
+    pub rate_bps:u32
+}
+
+#[derive(Clone,AnchorSerialize, AnchorDeserialize, PartialEq, Debug,InitSpace)]
+pub struct ThresholdStruct{
+    pub max_temp_centi_c:i32,
+    pub max_humidity_bps:u16,
+    pub max_breach_duration : u32,
+    // Seconds of shelf life lost per second spent in a temperature excursion.
+    pub excursion_shelf_life_factor:u16
+}
+
+// Returned by `evaluate_compliance` so clients can read the outcome through
+// simulation without committing anything to the batch.
+#[derive(Clone,AnchorSerialize, AnchorDeserialize, PartialEq, Debug)]
+pub struct ComplianceReport{
+    pub evaluated_at:i64,
+    pub iot_timestamp:i64,
+    pub iot_data_fresh:bool,
+    pub temperature_ok:bool,
+    pub humidity_ok:bool,
+    pub breach_reported:bool,
+    pub max_temp_centi_c:i32,
+    pub threshold_max_temp_centi_c:i32,
+    pub max_humidity_bps:u16,
+    pub threshold_max_humidity_bps:u16,
+    pub recent_summaries:u8,
+    pub recent_breaches:u8,
+    pub route_ok:bool,
+    pub compliant:bool
 }
 
 #[derive(Clone,AnchorSerialize, AnchorDeserialize, PartialEq, Debug,InitSpace)]
 pub struct ComplianceFlagsStruct{
     pub cold_chain_compliant:bool,
     pub fraud_detected:bool,
-    pub certification_issued:bool
+    pub certification_issued:bool,
+    pub route_deviation:bool
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 #[derive(InitSpace)]
 pub struct OriginDetails {
     pub production_date: i64,  
+    pub expiry_date: i64,
     pub quantity: u64,         
-    pub weight: f64,   
-    #[max_len(PRODUCT_TYPE_LENGTH)]
-    pub product_type: String,  
+    pub weight_grams: u64,
+    pub product: Pubkey,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
@@ -123,6 +535,10 @@ pub struct Event {
     pub details_hash: [u8; 32], 
     #[max_len(DETAILS_CID_LENGTH)]
     pub details_cid: String,
+    // Where the event was reported, for checkpoints, breaches and route
+    // deviations that carry a fix. Accuracy and fix time stay in the emitted
+    // log event to keep the stored log small.
+    pub location: Option<Waypoint>,
 }
 
 
@@ -135,7 +551,9 @@ pub enum Role{
     Retailer,
     Consumer,
     Regulator,
-    Administrator
+    Administrator,
+    Transporter,
+    Warehouse
 }
 
 #[derive(Clone,AnchorSerialize, AnchorDeserialize, PartialEq, Debug,InitSpace)]
@@ -147,7 +565,20 @@ pub enum BatchStatus{
     Sold,
     Flagged,
     Recalled,
-    Compliant
+    // No longer set: a compliance verdict only updates
+    // `compliance.cold_chain_compliant`. Kept so later variants keep their
+    // encoding.
+    Compliant,
+    Closed,
+    InStorage,
+    Expired
+}
+
+#[derive(Clone,AnchorSerialize, AnchorDeserialize, PartialEq, Debug,InitSpace)]
+pub enum ClosureReason{
+    SoldOut,
+    Consumed,
+    Destroyed
 }
 
 #[derive(Clone,AnchorSerialize, AnchorDeserialize, PartialEq, Debug,InitSpace)]
@@ -156,7 +587,36 @@ pub enum EventType{
     BreachDetected,
     ProcessingUpdate,
     StorageUpdate,
-    ComplianceCheck
+    ComplianceCheck,
+    FlagResolved,
+    FlagEscalated,
+    DisputeOpened,
+    DisputeResolved,
+    Sale,
+    Consumption,
+    Disposal,
+    Checkpoint,
+    CustodyTransfer,
+    OwnershipTransfer,
+    RouteDeviation,
+    Dispatch,
+    Expiry,
+    CertificationRevoked,
+    ShipmentCancelled
+}
+
+#[derive(Clone,AnchorSerialize, AnchorDeserialize, PartialEq, Debug,InitSpace)]
+pub enum ShipmentOutcome{
+    OnTime,
+    Late,
+    Cancelled
+}
+
+#[derive(Clone,AnchorSerialize, AnchorDeserialize, PartialEq, Debug,InitSpace)]
+pub enum DisputeStatus{
+    Open,
+    Upheld,
+    Rejected
 }
 
 
@@ -4,6 +4,7 @@ import { Contracts } from "../target/types/contracts";
 import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
 import { expect } from "chai";
 import { BN } from "bn.js";
+import { createHash } from "crypto";
 
 describe("Supply Chain Contracts", () => {
   const provider = anchor.AnchorProvider.env();
@@ -36,31 +37,66 @@ describe("Supply Chain Contracts", () => {
   const profileHash = Array.from({ length: 32 }, (_, i) => i + 1);
   const metadataHash = Array.from({ length: 32 }, (_, i) => i + 10);
   const detailsHash = Array.from({ length: 32 }, (_, i) => i + 20);
-  const metadataCid = "QmTestMetadataCID123";
-  const detailsCid = "QmTestDetailsCID456";
+  const metadataSchemaVersion = 1;
+  const metadataCid = "QmT13QYnUQxx28uA7BqnPdPE1dQpV3zp9Jy9z21dv3DqWn";
+  const detailsCid = "bafkreicb77mlo2x4sktfowh5tieav2aeehazn4srey5yps73nzlh3wqipe";
   const certHash = Array.from({ length: 32 }, (_, i) => i + 30);
-  const certCid = "QmTestCertCID789";
+  const certCid = "QmNkhsbALnM6mgAXoKuGWWuT2qZ33p54AcrA44DA2DKqqK";
   const iotHash = Array.from({ length: 32 }, (_, i) => i + 40);
-  const iotCid = "QmTestIoTCID012";
+  const iotCid = "bafkreih7hv6z4tecisakcil35bcuaetdxamw5op7ckl3wvc55m3uf4v6ua";
+
+  const spoofedBatchPda = new PublicKey("cGfHiC6Kgg3FpFZvgwGcswsCRtp4aBP2fzuXRQPizuN");
+  const productSku = "ORGANIC-APPLES";
+  const [productPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("product"), Buffer.from(productSku)],
+    program.programId
+  );
 
   const originDetails = {
     productionDate: new BN(Math.floor(Date.now() / 1000)),
+    expiryDate: new BN(Math.floor(Date.now() / 1000) + 30 * 24 * 3600),
     quantity: new BN(100),
-    weight: 50.5,
-    productType: "Organic Apples",
+    weightGrams: new BN(50_500),
+    product: productPda,
   };
 
   const iotSummary = {
     timestamp: Math.floor(Date.now() / 1000),
-    minTemp: 2.0,
-    maxTemp: 8.0,
-    avgTemp: 5.0,
-    minHumidity: 40.0,
-    maxHumidity: 60.0,
-    avgHumidity: 50.0,
+    minTempCentiC: 200,
+    maxTempCentiC: 800,
+    avgTempCentiC: 500,
+    minHumidityBps: 4_000,
+    maxHumidityBps: 6_000,
+    avgHumidityBps: 5_000,
     locationSummary: "Warehouse A, Zone 1",
     breachDetected: false,
     breachCount: 0,
+    location: null,
+  };
+
+  const legPda = (batch: PublicKey, legIndex: number) => {
+    const index = Buffer.alloc(2);
+    index.writeUInt16LE(legIndex);
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("leg"), batch.toBuffer(), index],
+      program.programId
+    )[0];
+  };
+
+  const shipmentPlanPda = (batch: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("shipment"), batch.toBuffer()],
+      program.programId
+    )[0];
+
+  // Leg accounts for the next custody move, plus the shipment plan if one is open.
+  const handoverLegs = async (batch: PublicKey) => {
+    const { currentLeg, shipmentOpen } = await program.account.batch.fetch(batch);
+    return {
+      currentLeg: legPda(batch, currentLeg),
+      nextLeg: legPda(batch, currentLeg + 1),
+      shipmentPlan: shipmentOpen ? shipmentPlanPda(batch) : null,
+    };
   };
 
   before(async () => {
@@ -120,12 +156,12 @@ describe("Supply Chain Contracts", () => {
     );
 
     [batchPda] = PublicKey.findProgramAddressSync(
-      [Buffer.from("batch"), Buffer.from(batchId)],
+      [Buffer.from("batch"), producer.publicKey.toBuffer(), Buffer.from(batchId)],
       program.programId
     );
 
     [certificationPda] = PublicKey.findProgramAddressSync(
-      [Buffer.from("cert"), Buffer.from(batchId), Buffer.from("organic")],
+      [Buffer.from("cert"), batchPda.toBuffer(), Buffer.from("organic")],
       program.programId
     );
   });
@@ -148,6 +184,43 @@ describe("Supply Chain Contracts", () => {
       expect(configAccount.adminWallet.toString()).to.equal(admin.publicKey.toString());
       expect(configAccount.oracleWallet.toString()).to.equal(oracle.publicKey.toString());
     });
+
+    it("Should let the admin update the policy", async () => {
+      const { policy } = await program.account.systemConfig.fetch(systemConfigPda);
+      expect(policy.version).to.equal(1);
+      expect(policy.iotStalenessSeconds.toNumber()).to.equal(3600);
+
+      await program.methods
+        .updatePolicy({ ...policy, iotStalenessSeconds: new BN(7200) })
+        .accounts({
+          admin: admin.publicKey,
+          systemConfig: systemConfigPda,
+        })
+        .signers([admin])
+        .rpc();
+
+      const configAccount = await program.account.systemConfig.fetch(systemConfigPda);
+      expect(configAccount.policy.version).to.equal(2);
+      expect(configAccount.policy.iotStalenessSeconds.toNumber()).to.equal(7200);
+    });
+
+    it("Should reject a policy update based on a stale version", async () => {
+      const { policy } = await program.account.systemConfig.fetch(systemConfigPda);
+
+      try {
+        await program.methods
+          .updatePolicy({ ...policy, version: policy.version - 1, maxHandovers: 5 })
+          .accounts({
+            admin: admin.publicKey,
+            systemConfig: systemConfigPda,
+          })
+          .signers([admin])
+          .rpc();
+        expect.fail("Should have thrown an error");
+      } catch (error) {
+        expect(error.message).to.include("PolicyVersionMismatch");
+      }
+    });
   });
 
   describe("Register Users", () => {
@@ -307,12 +380,179 @@ describe("Supply Chain Contracts", () => {
     });
   });
 
+  describe("Role Transitions", () => {
+    const [roleTransitionsPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("role_transitions")],
+      program.programId
+    );
+
+    it("Should seed the role-transition matrix with the default supply chain", async () => {
+      await program.methods
+        .initializeRoleTransitions()
+        .accounts({
+          admin: admin.publicKey,
+          systemConfig: systemConfigPda,
+        })
+        .signers([admin])
+        .rpc();
+
+      const transitions = await program.account.roleTransitions.fetch(roleTransitionsPda);
+      // Producer (1) -> Processor (2), Distributor (3), Retailer (4)
+      expect(transitions.allowed[1]).to.equal(0b11100);
+      // Retailer (4) hands nothing on by default
+      expect(transitions.allowed[4]).to.equal(0);
+    });
+
+    it("Should let the admin allow retailer to consumer handovers", async () => {
+      await program.methods
+        .setRoleTransitions({ retailer: {} }, [{ consumer: {} }])
+        .accounts({
+          admin: admin.publicKey,
+          systemConfig: systemConfigPda,
+        })
+        .signers([admin])
+        .rpc();
+
+      const transitions = await program.account.roleTransitions.fetch(roleTransitionsPda);
+      expect(transitions.allowed[4]).to.equal(1 << 5);
+    });
+
+    it("Should reject transition updates from non-admins", async () => {
+      try {
+        await program.methods
+          .setRoleTransitions({ distributor: {} }, [{ distributor: {} }])
+          .accounts({
+            admin: producer.publicKey,
+            systemConfig: systemConfigPda,
+          })
+          .signers([producer])
+          .rpc();
+        expect.fail("Should have thrown an error");
+      } catch (error) {
+        expect(error.message).to.include("Unauthorized");
+      }
+    });
+  });
+
+  describe("Product Catalog", () => {
+    const degradation = [
+      { maxTempCentiC: 400, rateBps: 10_000 },
+      { maxTempCentiC: 800, rateBps: 30_000 },
+      { maxTempCentiC: 2_500, rateBps: 80_000 },
+    ];
+    const productDetails = {
+      name: "Organic Apples",
+      category: "Fresh Produce",
+      brandOwner: producer.publicKey,
+      defaultThreshold: {
+        maxTempCentiC: 800,
+        maxHumidityBps: 9_000,
+        maxBreachDuration: 1_800,
+        excursionShelfLifeFactor: 0,
+      },
+      shelfLifeSeconds: new BN(20 * 24 * 3600),
+      degradation,
+    };
+
+    it("Should let the admin register a product", async () => {
+      await program.methods
+        .registerProduct(productSku, productDetails)
+        .accounts({
+          admin: admin.publicKey,
+          systemConfig: systemConfigPda,
+        })
+        .signers([admin])
+        .rpc();
+
+      const product = await program.account.product.fetch(productPda);
+      expect(product.sku).to.equal(productSku);
+      expect(product.name).to.equal("Organic Apples");
+      expect(product.brandOwner.toString()).to.equal(producer.publicKey.toString());
+      expect(product.shelfLifeSeconds.toNumber()).to.equal(20 * 24 * 3600);
+      expect(product.degradation).to.have.length(3);
+    });
+
+    it("Should reject product registration from anyone but the admin", async () => {
+      try {
+        await program.methods
+          .registerProduct("LOOSE-LEAF-TEA", {
+            ...productDetails,
+            name: "Loose Leaf Tea",
+            category: "Dry Goods",
+            shelfLifeSeconds: new BN(0),
+            degradation: [],
+          })
+          .accounts({
+            admin: producer.publicKey,
+            systemConfig: systemConfigPda,
+          })
+          .signers([producer])
+          .rpc();
+        expect.fail("Should have thrown an error");
+      } catch (error) {
+        expect(error.message).to.include("Unauthorized");
+      }
+    });
+
+    it("Should reject SKUs that are not in canonical form", async () => {
+      try {
+        await program.methods
+          .registerProduct("organic-apples", productDetails)
+          .accounts({
+            admin: admin.publicKey,
+            systemConfig: systemConfigPda,
+          })
+          .signers([admin])
+          .rpc();
+        expect.fail("Should have thrown an error");
+      } catch (error) {
+        expect(error.message).to.include("InvalidProduct");
+      }
+    });
+
+    it("Should reject degradation bands that are not in ascending temperature order", async () => {
+      try {
+        await program.methods
+          .updateProduct({ ...productDetails, degradation: [degradation[1], degradation[0]] })
+          .accounts({
+            product: productPda,
+            admin: admin.publicKey,
+            systemConfig: systemConfigPda,
+          })
+          .signers([admin])
+          .rpc();
+        expect.fail("Should have thrown an error");
+      } catch (error) {
+        expect(error.message).to.include("InvalidProduct");
+      }
+    });
+
+    it("Should reject product updates from anyone but the admin", async () => {
+      try {
+        await program.methods
+          .updateProduct(productDetails)
+          .accounts({
+            product: productPda,
+            admin: producer.publicKey,
+            systemConfig: systemConfigPda,
+          })
+          .signers([producer])
+          .rpc();
+        expect.fail("Should have thrown an error");
+      } catch (error) {
+        expect(error.message).to.include("Unauthorized");
+      }
+    });
+  });
+
   describe("Create Batch", () => {
     it("Should create batch successfully", async () => {
       await program.methods
-        .createBatch(batchId, originDetails, metadataHash, metadataCid)
+        .createBatch(batchId, originDetails, metadataHash, metadataCid, metadataSchemaVersion, null)
         .accounts({
           batch: batchPda,
+          product: productPda,
+          route: null,
           userProfile: producerProfilePda,
           user: producer.publicKey,
           systemProgram: SystemProgram.programId,
@@ -326,7 +566,155 @@ describe("Supply Chain Contracts", () => {
       expect(batch.currentOwner.toString()).to.equal(producer.publicKey.toString());
       expect(batch.status).to.deep.equal({ registered: {} });
       expect(batch.metadataCid).to.equal(metadataCid);
+      expect(batch.metadataSchemaVersion).to.equal(metadataSchemaVersion);
       expect(batch.events).to.have.length(0);
+      expect(batch.threshold.maxTempCentiC).to.equal(800);
+
+      const [, batchBump] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch"), producer.publicKey.toBuffer(), Buffer.from(batchId)],
+        program.programId
+      );
+      expect(batch.bump).to.equal(batchBump);
+      expect(batch.remainingShelfLifeSeconds.toNumber()).to.equal(20 * 24 * 3600);
+      expect(batch.degradation).to.have.length(3);
+    });
+
+    it("Should reject a metadata CID that is not a well-formed CID", async () => {
+      const [badCidBatchPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch"), producer.publicKey.toBuffer(), Buffer.from("BAD_CID_BATCH")],
+        program.programId
+      );
+
+      try {
+        await program.methods
+          .createBatch("BAD_CID_BATCH", originDetails, metadataHash, "QmNotARealContentIdentifier", metadataSchemaVersion, null)
+          .accounts({
+            batch: badCidBatchPda,
+            product: productPda,
+            route: null,
+            userProfile: producerProfilePda,
+            user: producer.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([producer])
+          .rpc();
+        expect.fail("Should have thrown an error");
+      } catch (error) {
+        expect(error.message).to.include("InvalidMetadataCid");
+      }
+    });
+
+    it("Should reject a zero metadata schema version", async () => {
+      const [unversionedBatchPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch"), producer.publicKey.toBuffer(), Buffer.from("UNVERSIONED_BATCH")],
+        program.programId
+      );
+
+      try {
+        await program.methods
+          .createBatch("UNVERSIONED_BATCH", originDetails, metadataHash, metadataCid, 0, null)
+          .accounts({
+            batch: unversionedBatchPda,
+            product: productPda,
+            route: null,
+            userProfile: producerProfilePda,
+            user: producer.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([producer])
+          .rpc();
+        expect.fail("Should have thrown an error");
+      } catch (error) {
+        expect(error.message).to.include("InvalidMetadataSchemaVersion");
+      }
+    });
+
+    it("Should reject a product account that differs from the origin details", async () => {
+      const [teaPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("product"), Buffer.from("LOOSE-LEAF-TEA")],
+        program.programId
+      );
+      const [mismatchBatchPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch"), producer.publicKey.toBuffer(), Buffer.from("MISMATCHED_PRODUCT")],
+        program.programId
+      );
+
+      try {
+        await program.methods
+          .createBatch("MISMATCHED_PRODUCT", originDetails, metadataHash, metadataCid, metadataSchemaVersion, null)
+          .accounts({
+            batch: mismatchBatchPda,
+            product: teaPda,
+            route: null,
+            userProfile: producerProfilePda,
+            user: producer.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([producer])
+          .rpc();
+        expect.fail("Should have thrown an error");
+      } catch (error) {
+        expect(error.message).to.include("ProductMismatch");
+      }
+    });
+
+    it("Should let another producer register the same batch id under its own key", async () => {
+      const rival = Keypair.generate();
+      await provider.connection.confirmTransaction(
+        await provider.connection.requestAirdrop(rival.publicKey, anchor.web3.LAMPORTS_PER_SOL)
+      );
+      const [rivalProfilePda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("user"), rival.publicKey.toBuffer()],
+        program.programId
+      );
+      const [rivalBatchPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch"), rival.publicKey.toBuffer(), Buffer.from(batchId)],
+        program.programId
+      );
+
+      await program.methods
+        .registerUser(profileHash)
+        .accounts({
+          user: rival.publicKey,
+          userProfile: rivalProfilePda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([rival])
+        .rpc();
+      await program.methods
+        .approveUser({ producer: {} })
+        .accounts({
+          admin: admin.publicKey,
+          userProfile: rivalProfilePda,
+          systemConfig: systemConfigPda,
+        })
+        .signers([admin])
+        .rpc();
+
+      await program.methods
+        .createBatch(batchId, originDetails, metadataHash, metadataCid, metadataSchemaVersion, null)
+        .accounts({
+          batch: rivalBatchPda,
+          product: productPda,
+          route: null,
+          userProfile: rivalProfilePda,
+          user: rival.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([rival])
+        .rpc();
+
+      // Both producers' batches are listed under the one id.
+      const [batchIndexPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch_id"), Buffer.from(batchId)],
+        program.programId
+      );
+      const index = await program.account.batchIdIndex.fetch(batchIndexPda);
+      expect(index.id).to.equal(batchId);
+      expect(index.entries.map((entry) => [entry.producer.toString(), entry.batch.toString()])).to.deep.equal([
+        [producer.publicKey.toString(), batchPda.toString()],
+        [rival.publicKey.toString(), rivalBatchPda.toString()],
+      ]);
     });
   });
 
@@ -337,6 +725,7 @@ describe("Supply Chain Contracts", () => {
         .accounts({
           batch: batchPda,
           oracle: oracle.publicKey,
+          route: null,
           systemConfig: systemConfigPda,
         })
         .signers([oracle])
@@ -344,14 +733,128 @@ describe("Supply Chain Contracts", () => {
 
       const batch = await program.account.batch.fetch(batchPda);
       expect(batch.iotSummary.timestamp).to.equal(iotSummary.timestamp);
-      expect(batch.iotSummary.minTemp).to.equal(iotSummary.minTemp);
-      expect(batch.iotSummary.maxTemp).to.equal(iotSummary.maxTemp);
+      expect(batch.iotSummary.minTempCentiC).to.equal(iotSummary.minTempCentiC);
+      expect(batch.iotSummary.maxTempCentiC).to.equal(iotSummary.maxTempCentiC);
       expect(batch.iotCid).to.equal(iotCid);
     });
+
+    it("Should append the summary to the batch IoT history", async () => {
+      const [iotHistoryPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("iot_history"), batchPda.toBuffer()],
+        program.programId
+      );
+
+      const history = await program.account.ioTHistory.fetch(iotHistoryPda);
+      expect(history.batch.toString()).to.equal(batchPda.toString());
+      expect(history.entries).to.have.length(1);
+      expect(history.head).to.equal(1);
+      expect(history.entries[0].iotHash).to.deep.equal(iotHash);
+      expect(history.entries[0].maxTempCentiC).to.equal(iotSummary.maxTempCentiC);
+    });
+
+    it("Should reject humidity above 100%", async () => {
+      try {
+        await program.methods
+          .updateIotSummary({ ...iotSummary, timestamp: iotSummary.timestamp + 1, maxHumidityBps: 10_001 }, iotHash, iotCid)
+          .accounts({
+            batch: batchPda,
+            oracle: oracle.publicKey,
+            route: null,
+          route: null,
+            systemConfig: systemConfigPda,
+          })
+          .signers([oracle])
+          .rpc();
+        expect.fail("Expected updateIotSummary to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("InvalidHumidityValue");
+      }
+    });
+
+    it("Should reject an average temperature outside min/max", async () => {
+      try {
+        await program.methods
+          .updateIotSummary({ ...iotSummary, timestamp: iotSummary.timestamp + 1, avgTempCentiC: 900 }, iotHash, iotCid)
+          .accounts({
+            batch: batchPda,
+            oracle: oracle.publicKey,
+            route: null,
+          route: null,
+            systemConfig: systemConfigPda,
+          })
+          .signers([oracle])
+          .rpc();
+        expect.fail("Expected updateIotSummary to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("AverageTemperatureOutOfRange");
+      }
+    });
+
+    it("Should reject a breach flag without a breach count", async () => {
+      try {
+        await program.methods
+          .updateIotSummary({ ...iotSummary, timestamp: iotSummary.timestamp + 1, breachDetected: true, breachCount: 0 }, iotHash, iotCid)
+          .accounts({
+            batch: batchPda,
+            oracle: oracle.publicKey,
+            route: null,
+          route: null,
+            systemConfig: systemConfigPda,
+          })
+          .signers([oracle])
+          .rpc();
+        expect.fail("Expected updateIotSummary to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("BreachCountMismatch");
+      }
+    });
+
+    it("Should reject summaries dated in the future", async () => {
+      try {
+        await program.methods
+          .updateIotSummary({ ...iotSummary, timestamp: iotSummary.timestamp + 24 * 3600 }, iotHash, iotCid)
+          .accounts({
+            batch: batchPda,
+            oracle: oracle.publicKey,
+            route: null,
+          route: null,
+            systemConfig: systemConfigPda,
+          })
+          .signers([oracle])
+          .rpc();
+        expect.fail("Expected updateIotSummary to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("FutureTimestamp");
+      }
+    });
   });
 
   describe("Check Compliance", () => {
+    it("Should evaluate compliance without changing the batch", async () => {
+      const before = await program.account.batch.fetch(batchPda);
+
+      const report = await program.methods
+        .evaluateCompliance()
+        .accounts({
+          batch: batchPda,
+          systemConfig: systemConfigPda,
+        })
+        .view();
+
+      expect(report.iotTimestamp.toNumber()).to.equal(iotSummary.timestamp);
+      expect(report.iotDataFresh).to.be.true;
+      expect(report.compliant).to.be.true;
+      expect(report.maxTempCentiC).to.equal(iotSummary.maxTempCentiC);
+      expect(report.recentSummaries).to.equal(1);
+
+      const after = await program.account.batch.fetch(batchPda);
+      expect(after.status).to.deep.equal(before.status);
+      expect(after.events).to.have.length(before.events.length);
+    });
+
     it("Should check compliance successfully", async () => {
+      const before = await program.account.batch.fetch(batchPda);
+
       await program.methods
         .checkCompliance()
         .accounts({
@@ -364,8 +867,9 @@ describe("Supply Chain Contracts", () => {
         .rpc();
 
       const batch = await program.account.batch.fetch(batchPda);
-      expect(batch.compliance.certificationIssued).to.be.true;
-      expect(batch.status).to.deep.equal({ compliant: {} });
+      expect(batch.compliance.coldChainCompliant).to.be.true;
+      expect(batch.compliance.certificationIssued).to.be.false;
+      expect(batch.status).to.deep.equal(before.status);
     });
   });
 
@@ -383,6 +887,10 @@ describe("Supply Chain Contracts", () => {
         .signers([regulator])
         .rpc();
 
+      const batch = await program.account.batch.fetch(batchPda);
+      expect(batch.certificationCount).to.equal(1);
+      expect(batch.compliance.certificationIssued).to.be.true;
+
       const certification = await program.account.certification.fetch(certificationPda);
       expect(certification.batchId).to.equal(batchId);
       expect(certification.certType).to.equal("organic");
@@ -391,12 +899,155 @@ describe("Supply Chain Contracts", () => {
     });
   });
 
+  describe("Sync Certification Status", () => {
+    it("Should reject an account that is not a certification of the batch", async () => {
+      try {
+        await program.methods
+          .syncCertificationStatus()
+          .accounts({
+            batch: batchPda,
+            admin: admin.publicKey,
+            systemConfig: systemConfigPda,
+          })
+          .remainingAccounts([
+            { pubkey: producerProfilePda, isWritable: false, isSigner: false },
+          ])
+          .signers([admin])
+          .rpc();
+        expect.fail("Should have thrown an error");
+      } catch (error) {
+        expect(error.error.errorCode.code).to.equal("InvalidCertificationAccount");
+      }
+    });
+
+    it("Should recount certification_count from the batch's certifications", async () => {
+      await program.methods
+        .syncCertificationStatus()
+        .accounts({
+          batch: batchPda,
+          admin: admin.publicKey,
+          systemConfig: systemConfigPda,
+        })
+        .signers([admin])
+        .rpc();
+
+      let batch = await program.account.batch.fetch(batchPda);
+      expect(batch.certificationCount).to.equal(0);
+      expect(batch.compliance.certificationIssued).to.be.false;
+
+      await program.methods
+        .syncCertificationStatus()
+        .accounts({
+          batch: batchPda,
+          admin: admin.publicKey,
+          systemConfig: systemConfigPda,
+        })
+        .remainingAccounts([
+          { pubkey: certificationPda, isWritable: false, isSigner: false },
+        ])
+        .signers([admin])
+        .rpc();
+
+      batch = await program.account.batch.fetch(batchPda);
+      expect(batch.certificationCount).to.equal(1);
+      expect(batch.compliance.certificationIssued).to.be.true;
+    });
+  });
+
+  describe("Revoke Certification", () => {
+    const revokeReasonHash = Array.from({ length: 32 }, (_, i) => i + 35);
+
+    it("Should reject revocation by someone other than the issuer or admin", async () => {
+      try {
+        await program.methods
+          .revokeCertification(revokeReasonHash)
+          .accounts({
+            certification: certificationPda,
+            batch: batchPda,
+            revoker: producer.publicKey,
+            systemConfig: systemConfigPda,
+          })
+          .signers([producer])
+          .rpc();
+        expect.fail("Should have thrown an error");
+      } catch (error) {
+        expect(error.message).to.include("InvalidRole");
+      }
+    });
+
+    it("Should let the issuer revoke a certification and clear the flag", async () => {
+      await program.methods
+        .revokeCertification(revokeReasonHash)
+        .accounts({
+          certification: certificationPda,
+          batch: batchPda,
+          revoker: regulator.publicKey,
+          systemConfig: systemConfigPda,
+        })
+        .signers([regulator])
+        .rpc();
+
+      const certification = await program.account.certification.fetch(certificationPda);
+      expect(certification.valid).to.be.false;
+
+      const batch = await program.account.batch.fetch(batchPda);
+      expect(batch.certificationCount).to.equal(0);
+      expect(batch.compliance.certificationIssued).to.be.false;
+      expect(batch.events[batch.events.length - 1].eventType).to.deep.equal({ certificationRevoked: {} });
+    });
+
+    it("Should not revoke the same certification twice", async () => {
+      try {
+        await program.methods
+          .revokeCertification(revokeReasonHash)
+          .accounts({
+            certification: certificationPda,
+            batch: batchPda,
+            revoker: admin.publicKey,
+            systemConfig: systemConfigPda,
+          })
+          .signers([admin])
+          .rpc();
+        expect.fail("Should have thrown an error");
+      } catch (error) {
+        expect(error.message).to.include("CertificationAlreadyRevoked");
+      }
+    });
+  });
+
   describe("Log Handover", () => {
+    it("Should reject a program-owned batch stored away from its PDA", async () => {
+      // Loaded from tests/fixtures/spoofed_batch.json via Anchor.toml. It
+      // deserializes as a Batch and carries its canonical bump, but its
+      // address is not the one its seeds derive to.
+      try {
+        await program.methods
+          .logHandover(processor.publicKey, detailsHash, detailsCid)
+          .accounts({
+            batch: spoofedBatchPda,
+            currentLeg: legPda(batchPda, 0),
+            nextLeg: legPda(spoofedBatchPda, 1),
+            shipmentPlan: null,
+            fromUserProfile: producerProfilePda,
+            toUserProfile: processorProfilePda,
+            fromUser: producer.publicKey,
+            toUser: processor.publicKey,
+          })
+          .signers([producer, processor])
+          .rpc();
+        expect.fail("Should have thrown an error");
+      } catch (error) {
+        expect(error.message).to.include("caused by account: batch");
+        expect(error.message).to.include("ConstraintSeeds");
+      }
+    });
+
     it("Should log handover from producer to processor successfully", async () => {
       await program.methods
         .logHandover(processor.publicKey, detailsHash, detailsCid)
         .accounts({
           batch: batchPda,
+          ...(await handoverLegs(batchPda)),
           fromUserProfile: producerProfilePda,
           toUserProfile: processorProfilePda,
           fromUser: producer.publicKey,
@@ -408,6 +1059,7 @@ describe("Supply Chain Contracts", () => {
       const batch = await program.account.batch.fetch(batchPda);
       expect(batch.currentOwner.toString()).to.equal(processor.publicKey.toString());
       expect(batch.status).to.deep.equal({ inProcessing: {} });
+      expect(batch.handoverCount).to.equal(1);
       expect(batch.events).to.have.length(1);
       expect(batch.events[0].eventType).to.deep.equal({ handOver: {} });
     });
@@ -417,6 +1069,7 @@ describe("Supply Chain Contracts", () => {
         .logHandover(distributor.publicKey, detailsHash, detailsCid)
         .accounts({
           batch: batchPda,
+          ...(await handoverLegs(batchPda)),
           fromUserProfile: processorProfilePda,
           toUserProfile: distributorProfilePda,
           fromUser: processor.publicKey,
@@ -436,6 +1089,7 @@ describe("Supply Chain Contracts", () => {
         .logHandover(retailer.publicKey, detailsHash, detailsCid)
         .accounts({
           batch: batchPda,
+          ...(await handoverLegs(batchPda)),
           fromUserProfile: distributorProfilePda,
           toUserProfile: retailerProfilePda,
           fromUser: distributor.publicKey,
@@ -455,6 +1109,7 @@ describe("Supply Chain Contracts", () => {
         .logHandover(consumer.publicKey, detailsHash, detailsCid)
         .accounts({
           batch: batchPda,
+          ...(await handoverLegs(batchPda)),
           fromUserProfile: retailerProfilePda,
           toUserProfile: consumerProfilePda,
           fromUser: retailer.publicKey,
@@ -493,64 +1148,1888 @@ describe("Supply Chain Contracts", () => {
     });
   });
 
-  describe("System Integration Tests", () => {
-    it("Should handle complete supply chain flow", async () => {
-      const integrationBatchId = "INTEGRATION_BATCH";
-      const [integrationBatchPda] = PublicKey.findProgramAddressSync(
-        [Buffer.from("batch"), Buffer.from(integrationBatchId)],
-        program.programId
-      );
+  describe("Resolve and Escalate Flag", () => {
+    const reportHash = Array.from({ length: 32 }, (_, i) => i + 50);
+    const reportCid = "QmXFNANdnWAFW5Lpbf7aajuYPMYY4QPwvUWatNVDw1QGox";
 
-      // 1. Create batch
+    it("Should restore the pre-flag status when a flag is resolved", async () => {
       await program.methods
-        .createBatch(integrationBatchId, originDetails, metadataHash, metadataCid)
+        .resolveFlag(reportHash, reportCid)
         .accounts({
-          batch: integrationBatchPda,
-          userProfile: producerProfilePda,
-          user: producer.publicKey,
-          systemProgram: SystemProgram.programId,
+          batch: batchPda,
+          regulatorProfile: regulatorProfilePda,
+          regulator: regulator.publicKey,
         })
-        .signers([producer])
+        .signers([regulator])
         .rpc();
 
-      let batch = await program.account.batch.fetch(integrationBatchPda);
-      expect(batch.status).to.deep.equal({ registered: {} });
+      const batch = await program.account.batch.fetch(batchPda);
+      expect(batch.status).to.deep.equal(batch.preFlagStatus);
+      expect(batch.status).to.not.deep.equal({ flagged: {} });
+      expect(batch.events[batch.events.length - 1].eventType).to.deep.equal({ flagResolved: {} });
+      expect(batch.events[batch.events.length - 2].eventType).to.deep.equal({ breachDetected: {} });
+    });
 
-      // 2. Update IoT summary
+    it("Should reject resolving a batch that is not flagged", async () => {
+      try {
+        await program.methods
+          .resolveFlag(reportHash, reportCid)
+          .accounts({
+            batch: batchPda,
+            regulatorProfile: regulatorProfilePda,
+            regulator: regulator.publicKey,
+          })
+          .signers([regulator])
+          .rpc();
+        expect.fail("Expected resolveFlag to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("BatchNotFlagged");
+      }
+    });
+
+    it("Should escalate a flag into a recall", async () => {
       await program.methods
-        .updateIotSummary(iotSummary, iotHash, iotCid)
+        .flagBatch("Packaging damage reported")
         .accounts({
-          batch: integrationBatchPda,
-          oracle: oracle.publicKey,
+          batch: batchPda,
+          callerProfile: regulatorProfilePda,
+          caller: regulator.publicKey,
           systemConfig: systemConfigPda,
         })
-        .signers([oracle])
+        .signers([regulator])
         .rpc();
 
-      // 3. Check compliance
       await program.methods
-        .checkCompliance()
+        .escalateFlag(reportHash, reportCid)
         .accounts({
-          batch: integrationBatchPda,
-          callerProfile: regulatorProfilePda,
-          caller: regulator.publicKey,
-          systemConfig: systemConfigPda,
+          batch: batchPda,
+          regulatorProfile: regulatorProfilePda,
+          regulator: regulator.publicKey,
         })
         .signers([regulator])
         .rpc();
 
-      // 4. Issue certification
-      const [integrationCertPda] = PublicKey.findProgramAddressSync(
-        [Buffer.from("cert"), Buffer.from(integrationBatchId), Buffer.from("organic")],
+      const batch = await program.account.batch.fetch(batchPda);
+      expect(batch.status).to.deep.equal({ recalled: {} });
+      expect(batch.events[batch.events.length - 1].eventType).to.deep.equal({ flagEscalated: {} });
+    });
+  });
+
+  describe("Dispute IoT Breach", () => {
+    const disputeBatchId = "DISPUTE_BATCH";
+    const evidenceHash = Array.from({ length: 32 }, (_, i) => i + 60);
+    const evidenceCid = "bafkreihoqjipw5xasszuwry7cott3o7fdunocqxj35m5pqgtd3ba6cqkry";
+    const rulingHash = Array.from({ length: 32 }, (_, i) => i + 70);
+    let disputeBatchPda: PublicKey;
+    let disputePda: PublicKey;
+
+    before(async () => {
+      [disputeBatchPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch"), producer.publicKey.toBuffer(), Buffer.from(disputeBatchId)],
+        program.programId
+      );
+      [disputePda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("dispute"), disputeBatchPda.toBuffer(), Buffer.from(iotHash)],
         program.programId
       );
 
       await program.methods
-        .issueCertification("organic", certHash, certCid)
+        .createBatch(disputeBatchId, originDetails, metadataHash, metadataCid, metadataSchemaVersion, null)
         .accounts({
-          certification: integrationCertPda,
-          batch: integrationBatchPda,
-          issuerProfile: regulatorProfilePda,
+          batch: disputeBatchPda,
+          product: productPda,
+          route: null,
+          userProfile: producerProfilePda,
+          user: producer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([producer])
+        .rpc();
+
+      await program.methods
+        .updateIotSummary({ ...iotSummary, breachDetected: true, breachCount: 1 }, iotHash, iotCid)
+        .accounts({
+          batch: disputeBatchPda,
+          oracle: oracle.publicKey,
+          route: null,
+          systemConfig: systemConfigPda,
+        })
+        .signers([oracle])
+        .rpc();
+    });
+
+    it("Should attribute the breach to the custodian of the current leg", async () => {
+      const leg = await program.account.legSummary.fetch(legPda(disputeBatchPda, 0));
+      expect(leg.custodian.toString()).to.equal(producer.publicKey.toString());
+      expect(leg.summaryCount).to.equal(1);
+      expect(leg.excursionCount).to.equal(1);
+
+      const batch = await program.account.batch.fetch(disputeBatchPda);
+      const breach = batch.events.find((e) => e.eventType.breachDetected !== undefined);
+      expect(breach.toWallet.toString()).to.equal(producer.publicKey.toString());
+      expect(breach.detailsHash).to.deep.equal(iotHash);
+      expect(batch.iotSummary.breachCount).to.equal(1);
+    });
+
+    it("Should reject a dispute for a summary that is not in the history", async () => {
+      const unknownHash = Array.from({ length: 32 }, (_, i) => i + 90);
+      const [unknownDisputePda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("dispute"), disputeBatchPda.toBuffer(), Buffer.from(unknownHash)],
+        program.programId
+      );
+
+      try {
+        await program.methods
+          .openDispute(unknownHash, evidenceHash, evidenceCid)
+          .accounts({
+            dispute: unknownDisputePda,
+            batch: disputeBatchPda,
+            ownerProfile: producerProfilePda,
+            regulatorProfile: regulatorProfilePda,
+            owner: producer.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([producer])
+          .rpc();
+        expect.fail("Should have thrown an error");
+      } catch (error) {
+        expect(error.message).to.include("SummaryNotInHistory");
+      }
+    });
+
+    it("Should let the batch owner dispute an earlier summary after a newer one arrives", async () => {
+      const laterHash = Array.from({ length: 32 }, (_, i) => i + 95);
+      await program.methods
+        .updateIotSummary({ ...iotSummary, timestamp: iotSummary.timestamp + 60 }, laterHash, iotCid)
+        .accounts({
+          batch: disputeBatchPda,
+          oracle: oracle.publicKey,
+          route: null,
+          systemConfig: systemConfigPda,
+        })
+        .signers([oracle])
+        .rpc();
+
+      await program.methods
+        .openDispute(iotHash, evidenceHash, evidenceCid)
+        .accounts({
+          dispute: disputePda,
+          batch: disputeBatchPda,
+          ownerProfile: producerProfilePda,
+          regulatorProfile: regulatorProfilePda,
+          owner: producer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([producer])
+        .rpc();
+
+      const dispute = await program.account.dispute.fetch(disputePda);
+      expect(dispute.regulator.toString()).to.equal(regulator.publicKey.toString());
+      expect(dispute.iotHash).to.deep.equal(iotHash);
+      expect(dispute.status).to.deep.equal({ open: {} });
+    });
+
+    it("Should restore cold chain compliance when the dispute is upheld", async () => {
+      await program.methods
+        .ruleDispute(true, rulingHash)
+        .accounts({
+          dispute: disputePda,
+          batch: disputeBatchPda,
+          regulatorProfile: regulatorProfilePda,
+          regulator: regulator.publicKey,
+        })
+        .signers([regulator])
+        .rpc();
+
+      const dispute = await program.account.dispute.fetch(disputePda);
+      expect(dispute.status).to.deep.equal({ upheld: {} });
+
+      const batch = await program.account.batch.fetch(disputeBatchPda);
+      expect(batch.compliance.coldChainCompliant).to.be.true;
+      expect(batch.events[batch.events.length - 1].eventType).to.deep.equal({ disputeResolved: {} });
+    });
+
+    it("Should keep the batch non-compliant while an undisputed breach stands", async () => {
+      const standingHash = Array.from({ length: 32 }, (_, i) => i + 100);
+      const disputedHash = Array.from({ length: 32 }, (_, i) => i + 110);
+      const [secondDisputePda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("dispute"), disputeBatchPda.toBuffer(), Buffer.from(disputedHash)],
+        program.programId
+      );
+
+      for (const [hash, offset] of [[standingHash, 120], [disputedHash, 180]] as const) {
+        await program.methods
+          .updateIotSummary(
+            { ...iotSummary, timestamp: iotSummary.timestamp + offset, breachDetected: true, breachCount: 1 },
+            hash,
+            iotCid
+          )
+          .accounts({
+            batch: disputeBatchPda,
+            oracle: oracle.publicKey,
+            route: null,
+            systemConfig: systemConfigPda,
+          })
+          .signers([oracle])
+          .rpc();
+      }
+
+      await program.methods
+        .openDispute(disputedHash, evidenceHash, evidenceCid)
+        .accounts({
+          dispute: secondDisputePda,
+          batch: disputeBatchPda,
+          ownerProfile: producerProfilePda,
+          regulatorProfile: regulatorProfilePda,
+          owner: producer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([producer])
+        .rpc();
+
+      await program.methods
+        .ruleDispute(true, rulingHash)
+        .accounts({
+          dispute: secondDisputePda,
+          batch: disputeBatchPda,
+          regulatorProfile: regulatorProfilePda,
+          regulator: regulator.publicKey,
+        })
+        .signers([regulator])
+        .rpc();
+
+      const batch = await program.account.batch.fetch(disputeBatchPda);
+      expect(batch.compliance.coldChainCompliant).to.be.false;
+
+      const [historyPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("iot_history"), disputeBatchPda.toBuffer()],
+        program.programId
+      );
+      const history = await program.account.ioTHistory.fetch(historyPda);
+      const overturned = history.entries.filter((e) => e.breachOverturned).map((e) => e.iotHash);
+      expect(overturned).to.deep.include(disputedHash);
+      expect(overturned).to.not.deep.include(standingHash);
+    });
+  });
+
+  describe("Retail Sale and Closure", () => {
+    const saleBatchId = "SALE_BATCH";
+    const disposalBatchId = "DISPOSAL_BATCH";
+    const saleHash = Array.from({ length: 32 }, (_, i) => i + 80);
+    const disposalCid = "QmRWEByPEWQtagxE1heknLdWj5ZRrgZg3hZGMNvtMo1RU3";
+    let saleBatchPda: PublicKey;
+    let disposalBatchPda: PublicKey;
+
+    before(async () => {
+      [saleBatchPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch"), producer.publicKey.toBuffer(), Buffer.from(saleBatchId)],
+        program.programId
+      );
+      [disposalBatchPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch"), producer.publicKey.toBuffer(), Buffer.from(disposalBatchId)],
+        program.programId
+      );
+
+      for (const [id, pda] of [[saleBatchId, saleBatchPda], [disposalBatchId, disposalBatchPda]] as const) {
+        await program.methods
+          .createBatch(id, originDetails, metadataHash, metadataCid, metadataSchemaVersion, null)
+          .accounts({
+            batch: pda,
+            product: productPda,
+            route: null,
+            userProfile: producerProfilePda,
+            user: producer.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([producer])
+          .rpc();
+      }
+
+      await program.methods
+        .logHandover(retailer.publicKey, detailsHash, detailsCid)
+        .accounts({
+          batch: saleBatchPda,
+          ...(await handoverLegs(saleBatchPda)),
+          fromUserProfile: producerProfilePda,
+          toUserProfile: retailerProfilePda,
+          fromUser: producer.publicKey,
+          toUser: retailer.publicKey,
+        })
+        .signers([producer, retailer])
+        .rpc();
+    });
+
+    it("Should record a partial sale", async () => {
+      await program.methods
+        .recordSale(new BN(40), saleHash)
+        .accounts({
+          batch: saleBatchPda,
+          sellerProfile: retailerProfilePda,
+          seller: retailer.publicKey,
+        })
+        .signers([retailer])
+        .rpc();
+
+      const batch = await program.account.batch.fetch(saleBatchPda);
+      expect(batch.remainingQuantity.toNumber()).to.equal(60);
+      expect(batch.status).to.deep.equal({ sold: {} });
+    });
+
+    it("Should reject selling more than the remaining quantity", async () => {
+      try {
+        await program.methods
+          .recordSale(new BN(61), saleHash)
+          .accounts({
+            batch: saleBatchPda,
+            sellerProfile: retailerProfilePda,
+            seller: retailer.publicKey,
+          })
+          .signers([retailer])
+          .rpc();
+        expect.fail("Expected recordSale to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("InvalidQuantity");
+      }
+    });
+
+    it("Should close the batch once it is sold out", async () => {
+      await program.methods
+        .recordSale(new BN(60), saleHash)
+        .accounts({
+          batch: saleBatchPda,
+          sellerProfile: retailerProfilePda,
+          seller: retailer.publicKey,
+        })
+        .signers([retailer])
+        .rpc();
+
+      const batch = await program.account.batch.fetch(saleBatchPda);
+      expect(batch.remainingQuantity.toNumber()).to.equal(0);
+      expect(batch.status).to.deep.equal({ closed: {} });
+      expect(batch.closureReason).to.deep.equal({ soldOut: {} });
+    });
+
+    it("Should close a destroyed batch with a disposal proof", async () => {
+      await program.methods
+        .markDestroyed(saleHash, disposalCid)
+        .accounts({
+          batch: disposalBatchPda,
+          callerProfile: producerProfilePda,
+          caller: producer.publicKey,
+        })
+        .signers([producer])
+        .rpc();
+
+      const batch = await program.account.batch.fetch(disposalBatchPda);
+      expect(batch.status).to.deep.equal({ closed: {} });
+      expect(batch.closureReason).to.deep.equal({ destroyed: {} });
+      expect(batch.events[batch.events.length - 1].eventType).to.deep.equal({ disposal: {} });
+    });
+
+    it("Should refuse to flag a closed batch", async () => {
+      try {
+        await program.methods
+          .flagBatch("Fraud suspected after disposal")
+          .accounts({
+            batch: disposalBatchPda,
+            callerProfile: regulatorProfilePda,
+            caller: regulator.publicKey,
+            systemConfig: systemConfigPda,
+          })
+          .signers([regulator])
+          .rpc();
+        expect.fail("Should have thrown an error");
+      } catch (error) {
+        expect(error.message).to.include("BatchAlreadyClosed");
+      }
+    });
+
+    it("Should refuse to check compliance on a closed batch", async () => {
+      try {
+        await program.methods
+          .checkCompliance()
+          .accounts({
+            batch: disposalBatchPda,
+            callerProfile: regulatorProfilePda,
+            caller: regulator.publicKey,
+            systemConfig: systemConfigPda,
+          })
+          .signers([regulator])
+          .rpc();
+        expect.fail("Should have thrown an error");
+      } catch (error) {
+        expect(error.message).to.include("BatchAlreadyClosed");
+      }
+
+      const batch = await program.account.batch.fetch(disposalBatchPda);
+      expect(batch.status).to.deep.equal({ closed: {} });
+    });
+
+    it("Should refuse to close another batch's accounts when archiving", async () => {
+      const [archivePda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("archive"), producer.publicKey.toBuffer(), Buffer.from(disposalBatchId)],
+        program.programId
+      );
+      const [disposalIndexPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch_id"), Buffer.from(disposalBatchId)],
+        program.programId
+      );
+
+      try {
+        await program.methods
+          .archiveBatch()
+          .accounts({
+            archive: archivePda,
+            batch: disposalBatchPda,
+            batchIndex: disposalIndexPda,
+            producer: producer.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .remainingAccounts([
+            { pubkey: legPda(disposalBatchPda, 0), isWritable: true, isSigner: false },
+            { pubkey: legPda(batchPda, 0), isWritable: true, isSigner: false },
+          ])
+          .signers([producer])
+          .rpc();
+        expect.fail("Expected archiveBatch to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("InvalidArchiveAccount");
+      }
+      expect(await provider.connection.getAccountInfo(legPda(batchPda, 0))).to.not.be.null;
+    });
+
+    it("Should archive a closed batch and refund the batch account", async () => {
+      const [archivePda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("archive"), producer.publicKey.toBuffer(), Buffer.from(saleBatchId)],
+        program.programId
+      );
+      const [saleIndexPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch_id"), Buffer.from(saleBatchId)],
+        program.programId
+      );
+      const closedBatch = await program.account.batch.fetch(saleBatchPda);
+
+      await program.methods
+        .archiveBatch()
+        .accounts({
+          archive: archivePda,
+          batch: saleBatchPda,
+          batchIndex: saleIndexPda,
+          producer: producer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts(
+          [legPda(saleBatchPda, 0), legPda(saleBatchPda, 1)].map((pubkey) => ({
+            pubkey,
+            isWritable: true,
+            isSigner: false,
+          }))
+        )
+        .signers([producer])
+        .rpc();
+
+      const archive = await program.account.batchArchive.fetch(archivePda);
+      expect(archive.id).to.equal(saleBatchId);
+      expect(archive.finalStatus).to.deep.equal({ closed: {} });
+      expect(archive.closureReason).to.deep.equal({ soldOut: {} });
+      expect(archive.eventCount).to.equal(closedBatch.events.length);
+      expect(await provider.connection.getAccountInfo(saleBatchPda)).to.be.null;
+      const [saleHistoryPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("iot_history"), saleBatchPda.toBuffer()],
+        program.programId
+      );
+      expect(await provider.connection.getAccountInfo(saleHistoryPda)).to.be.null;
+      expect(await provider.connection.getAccountInfo(saleIndexPda)).to.be.null;
+      expect(await provider.connection.getAccountInfo(legPda(saleBatchPda, 0))).to.be.null;
+      expect(await provider.connection.getAccountInfo(legPda(saleBatchPda, 1))).to.be.null;
+    });
+
+    it("Should refuse to reuse the ID of an archived batch", async () => {
+      try {
+        await program.methods
+          .createBatch(saleBatchId, originDetails, metadataHash, metadataCid, metadataSchemaVersion, null)
+          .accounts({
+            batch: saleBatchPda,
+            product: productPda,
+            route: null,
+            userProfile: producerProfilePda,
+            user: producer.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([producer])
+          .rpc();
+        expect.fail("Expected createBatch to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("BatchIdArchived");
+      }
+    });
+  });
+
+  describe("Consumer Claims", () => {
+    const claimBatchId = "CLAIM_BATCH";
+    const claimCode = Buffer.from(Array.from({ length: 32 }, (_, i) => i + 120));
+    const claimCodeHash = Array.from(createHash("sha256").update(claimCode).digest());
+    const walkInCustomer = Keypair.generate();
+    const frontRunner = Keypair.generate();
+    let claimBatchPda: PublicKey;
+    let claimPda: PublicKey;
+
+    const commitmentPda = (wallet: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("claim_commit"), claimPda.toBuffer(), wallet.toBuffer()],
+        program.programId
+      )[0];
+    const commitmentHash = (code: Buffer, wallet: PublicKey) =>
+      Array.from(createHash("sha256").update(code).update(wallet.toBuffer()).digest());
+
+    before(async () => {
+      [claimBatchPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch"), producer.publicKey.toBuffer(), Buffer.from(claimBatchId)],
+        program.programId
+      );
+      [claimPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("claim"), claimBatchPda.toBuffer(), Buffer.from(claimCodeHash)],
+        program.programId
+      );
+
+      for (const account of [walkInCustomer, frontRunner]) {
+        await provider.connection.confirmTransaction(
+          await provider.connection.requestAirdrop(account.publicKey, anchor.web3.LAMPORTS_PER_SOL)
+        );
+      }
+
+      await program.methods
+        .createBatch(claimBatchId, originDetails, metadataHash, metadataCid, metadataSchemaVersion, null)
+        .accounts({
+          batch: claimBatchPda,
+          product: productPda,
+          route: null,
+          userProfile: producerProfilePda,
+          user: producer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([producer])
+        .rpc();
+
+      await program.methods
+        .logHandover(retailer.publicKey, detailsHash, detailsCid)
+        .accounts({
+          batch: claimBatchPda,
+          ...(await handoverLegs(claimBatchPda)),
+          fromUserProfile: producerProfilePda,
+          toUserProfile: retailerProfilePda,
+          fromUser: producer.publicKey,
+          toUser: retailer.publicKey,
+        })
+        .signers([producer, retailer])
+        .rpc();
+    });
+
+    it("Should let the retailer cancel an unredeemed claim and release its units", async () => {
+      const lostCode = Buffer.from(Array.from({ length: 32 }, (_, i) => i + 60));
+      const lostCodeHash = Array.from(createHash("sha256").update(lostCode).digest());
+      const [lostClaimPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("claim"), claimBatchPda.toBuffer(), Buffer.from(lostCodeHash)],
+        program.programId
+      );
+      const [lostCommitmentPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("claim_commit"), lostClaimPda.toBuffer(), frontRunner.publicKey.toBuffer()],
+        program.programId
+      );
+
+      await program.methods
+        .issueConsumerClaim(lostCodeHash, originDetails.quantity)
+        .accounts({
+          claim: lostClaimPda,
+          batch: claimBatchPda,
+          retailerProfile: retailerProfilePda,
+          retailer: retailer.publicKey,
+        })
+        .signers([retailer])
+        .rpc();
+      await program.methods
+        .commitConsumerClaim(commitmentHash(lostCode, frontRunner.publicKey))
+        .accounts({
+          commitment: lostCommitmentPda,
+          claim: lostClaimPda,
+          consumer: frontRunner.publicKey,
+        })
+        .signers([frontRunner])
+        .rpc();
+
+      try {
+        await program.methods
+          .cancelConsumerClaim()
+          .accounts({
+            claim: lostClaimPda,
+            batch: claimBatchPda,
+            retailer: producer.publicKey,
+          })
+          .signers([producer])
+          .rpc();
+        expect.fail("Expected cancelConsumerClaim to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("ConstraintHasOne");
+      }
+
+      const consumerBalance = await provider.connection.getBalance(frontRunner.publicKey);
+      const commitmentRent = await provider.connection.getBalance(lostCommitmentPda);
+      await program.methods
+        .cancelConsumerClaim()
+        .accounts({
+          claim: lostClaimPda,
+          batch: claimBatchPda,
+          retailer: retailer.publicKey,
+        })
+        .remainingAccounts([
+          { pubkey: lostCommitmentPda, isWritable: true, isSigner: false },
+          { pubkey: frontRunner.publicKey, isWritable: true, isSigner: false },
+        ])
+        .signers([retailer])
+        .rpc();
+
+      expect(await provider.connection.getAccountInfo(lostClaimPda)).to.be.null;
+      expect(await provider.connection.getAccountInfo(lostCommitmentPda)).to.be.null;
+      expect(await provider.connection.getBalance(frontRunner.publicKey)).to.equal(consumerBalance + commitmentRent);
+      const batch = await program.account.batch.fetch(claimBatchPda);
+      expect(batch.reservedQuantity.toNumber()).to.equal(0);
+    });
+
+    it("Should let the retailer issue a claim for the remaining stock", async () => {
+      await program.methods
+        .issueConsumerClaim(claimCodeHash, originDetails.quantity)
+        .accounts({
+          claim: claimPda,
+          batch: claimBatchPda,
+          retailerProfile: retailerProfilePda,
+          retailer: retailer.publicKey,
+        })
+        .signers([retailer])
+        .rpc();
+
+      const claim = await program.account.consumerClaim.fetch(claimPda);
+      expect(claim.retailer.toString()).to.equal(retailer.publicKey.toString());
+      expect(claim.redeemedBy).to.be.null;
+
+      const batch = await program.account.batch.fetch(claimBatchPda);
+      expect(batch.reservedQuantity.toNumber()).to.equal(originDetails.quantity.toNumber());
+    });
+
+    it("Should not sell units reserved for an outstanding claim", async () => {
+      try {
+        await program.methods
+          .recordSale(new BN(1), Array.from({ length: 32 }, () => 9))
+          .accounts({
+            batch: claimBatchPda,
+            sellerProfile: retailerProfilePda,
+            seller: retailer.publicKey,
+          })
+          .signers([retailer])
+          .rpc();
+        expect.fail("Expected recordSale to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("InvalidQuantity");
+      }
+    });
+
+    it("Should let the consumer commit to the code before revealing it", async () => {
+      await program.methods
+        .commitConsumerClaim(commitmentHash(claimCode, walkInCustomer.publicKey))
+        .accounts({
+          commitment: commitmentPda(walkInCustomer.publicKey),
+          claim: claimPda,
+          consumer: walkInCustomer.publicKey,
+        })
+        .signers([walkInCustomer])
+        .rpc();
+
+      const commitment = await program.account.claimCommitment.fetch(commitmentPda(walkInCustomer.publicKey));
+      expect(commitment.consumer.toString()).to.equal(walkInCustomer.publicKey.toString());
+    });
+
+    it("Should reject a redemption with the wrong code", async () => {
+      try {
+        await program.methods
+          .redeemConsumerClaim(Array.from({ length: 32 }, () => 7))
+          .accounts({
+            claim: claimPda,
+            batch: claimBatchPda,
+            commitment: commitmentPda(walkInCustomer.publicKey),
+            consumer: walkInCustomer.publicKey,
+          })
+          .signers([walkInCustomer])
+          .rpc();
+        expect.fail("Expected redeemConsumerClaim to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("InvalidClaimCode");
+      }
+    });
+
+    it("Should not let another wallet redeem a copied code without its own earlier commitment", async () => {
+      try {
+        await program.methods
+          .redeemConsumerClaim(Array.from(claimCode))
+          .accounts({
+            claim: claimPda,
+            batch: claimBatchPda,
+            commitment: commitmentPda(frontRunner.publicKey),
+            consumer: frontRunner.publicKey,
+          })
+          .signers([frontRunner])
+          .rpc();
+        expect.fail("Expected redeemConsumerClaim to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("AccountNotInitialized");
+      }
+    });
+
+    it("Should hand final custody to an unregistered consumer wallet", async () => {
+      await program.methods
+        .redeemConsumerClaim(Array.from(claimCode))
+        .accounts({
+          claim: claimPda,
+          batch: claimBatchPda,
+          commitment: commitmentPda(walkInCustomer.publicKey),
+          consumer: walkInCustomer.publicKey,
+        })
+        .signers([walkInCustomer])
+        .rpc();
+
+      const claim = await program.account.consumerClaim.fetch(claimPda);
+      expect(claim.redeemedBy.toString()).to.equal(walkInCustomer.publicKey.toString());
+      expect(await provider.connection.getAccountInfo(commitmentPda(walkInCustomer.publicKey))).to.be.null;
+
+      const batch = await program.account.batch.fetch(claimBatchPda);
+      expect(batch.currentOwner.toString()).to.equal(walkInCustomer.publicKey.toString());
+      expect(batch.reservedQuantity.toNumber()).to.equal(0);
+      expect(batch.status).to.deep.equal({ closed: {} });
+      expect(batch.closureReason).to.deep.equal({ soldOut: {} });
+      const last = batch.events[batch.events.length - 1];
+      expect(last.eventType).to.deep.equal({ handOver: {} });
+      expect(last.toWallet.toString()).to.equal(walkInCustomer.publicKey.toString());
+    });
+
+    it("Should not accept commitments for a redeemed claim", async () => {
+      try {
+        await program.methods
+          .commitConsumerClaim(commitmentHash(claimCode, consumer.publicKey))
+          .accounts({
+            commitment: commitmentPda(consumer.publicKey),
+            claim: claimPda,
+            consumer: consumer.publicKey,
+          })
+          .signers([consumer])
+          .rpc();
+        expect.fail("Expected commitConsumerClaim to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("ClaimAlreadyRedeemed");
+      }
+    });
+
+    it("Should not cancel a redeemed claim", async () => {
+      try {
+        await program.methods
+          .cancelConsumerClaim()
+          .accounts({
+            claim: claimPda,
+            batch: claimBatchPda,
+            retailer: retailer.publicKey,
+          })
+          .signers([retailer])
+          .rpc();
+        expect.fail("Expected cancelConsumerClaim to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("ClaimAlreadyRedeemed");
+      }
+    });
+  });
+
+  describe("Logistics Checkpoints", () => {
+    const logisticsBatchId = "LOGISTICS_BATCH";
+    const transporter = Keypair.generate();
+    const warehouse = Keypair.generate();
+    let logisticsBatchPda: PublicKey;
+    let transporterProfilePda: PublicKey;
+    let warehouseProfilePda: PublicKey;
+    let warehouseBindingPda: PublicKey;
+    let logisticsRoutePda: PublicKey;
+    // Degrees scaled by 1e7.
+    const rotterdam = { latE7: 519_225_000, lonE7: 44_791_700 };
+    const amsterdam = { latE7: 523_676_000, lonE7: 49_041_000 };
+    const utrecht = { latE7: 520_907_000, lonE7: 51_214_000 };
+    const fix = (point: { latE7: number; lonE7: number }) => ({
+      ...point,
+      accuracyM: 25,
+      timestamp: new BN(Math.floor(Date.now() / 1000)),
+    });
+
+    before(async () => {
+      [logisticsBatchPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch"), producer.publicKey.toBuffer(), Buffer.from(logisticsBatchId)],
+        program.programId
+      );
+      [transporterProfilePda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("user"), transporter.publicKey.toBuffer()],
+        program.programId
+      );
+      [warehouseProfilePda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("user"), warehouse.publicKey.toBuffer()],
+        program.programId
+      );
+      [warehouseBindingPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("service"), logisticsBatchPda.toBuffer(), producer.publicKey.toBuffer(), warehouse.publicKey.toBuffer()],
+        program.programId
+      );
+      [logisticsRoutePda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("route"), logisticsBatchPda.toBuffer()],
+        program.programId
+      );
+
+      for (const account of [transporter, warehouse]) {
+        await provider.connection.requestAirdrop(account.publicKey, 2 * anchor.web3.LAMPORTS_PER_SOL);
+      }
+      await new Promise(resolve => setTimeout(resolve, 2000));
+
+      for (const [account, role] of [[transporter, { transporter: {} }], [warehouse, { warehouse: {} }]] as const) {
+        await program.methods
+          .registerUser(profileHash)
+          .accounts({ user: account.publicKey })
+          .signers([account])
+          .rpc();
+        const [profilePda] = PublicKey.findProgramAddressSync(
+          [Buffer.from("user"), account.publicKey.toBuffer()],
+          program.programId
+        );
+        await program.methods
+          .approveUser(role)
+          .accounts({
+            admin: admin.publicKey,
+            userProfile: profilePda,
+            systemConfig: systemConfigPda,
+          })
+          .signers([admin])
+          .rpc();
+      }
+
+      await program.methods
+        .createBatch(logisticsBatchId, originDetails, metadataHash, metadataCid, metadataSchemaVersion, null)
+        .accounts({
+          batch: logisticsBatchPda,
+          product: productPda,
+          route: null,
+          userProfile: producerProfilePda,
+          user: producer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([producer])
+        .rpc();
+    });
+
+    it("Should define a route corridor before the batch leaves the producer", async () => {
+      await program.methods
+        .defineRoute([rotterdam, amsterdam], 5_000, { ...amsterdam, radiusM: 10_000 })
+        .accounts({
+          route: logisticsRoutePda,
+          batch: logisticsBatchPda,
+          producer: producer.publicKey,
+        })
+        .signers([producer])
+        .rpc();
+
+      const route = await program.account.routeCorridor.fetch(logisticsRoutePda);
+      expect(route.waypoints).to.have.length(2);
+      const batch = await program.account.batch.fetch(logisticsBatchPda);
+      expect(batch.routeDefined).to.be.true;
+    });
+
+    it("Should accept a route corridor at batch creation", async () => {
+      const routedBatchId = "ROUTED_BATCH";
+      const [routedBatchPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch"), producer.publicKey.toBuffer(), Buffer.from(routedBatchId)],
+        program.programId
+      );
+      const [routedRoutePda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("route"), routedBatchPda.toBuffer()],
+        program.programId
+      );
+
+      await program.methods
+        .createBatch(routedBatchId, originDetails, metadataHash, metadataCid, metadataSchemaVersion, {
+          waypoints: [rotterdam, amsterdam],
+          corridorWidthM: 5_000,
+          destination: { ...amsterdam, radiusM: 10_000 },
+        })
+        .accounts({
+          batch: routedBatchPda,
+          product: productPda,
+          route: routedRoutePda,
+          userProfile: producerProfilePda,
+          user: producer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([producer])
+        .rpc();
+
+      const route = await program.account.routeCorridor.fetch(routedRoutePda);
+      expect(route.waypoints).to.have.length(2);
+      const batch = await program.account.batch.fetch(routedBatchPda);
+      expect(batch.routeDefined).to.be.true;
+    });
+
+    it("Should let the custodian log an on-route checkpoint", async () => {
+      await program.methods
+        .logCheckpoint(fix(rotterdam), detailsHash, detailsCid)
+        .accounts({
+          batch: logisticsBatchPda,
+          callerProfile: producerProfilePda,
+          caller: producer.publicKey,
+          serviceBinding: null,
+          route: logisticsRoutePda,
+        })
+        .signers([producer])
+        .rpc();
+
+      const batch = await program.account.batch.fetch(logisticsBatchPda);
+      const checkpoint = batch.events[batch.events.length - 1];
+      expect(checkpoint.eventType).to.deep.equal({ checkpoint: {} });
+      expect(checkpoint.location).to.deep.equal(rotterdam);
+      expect(batch.currentOwner.toString()).to.equal(producer.publicKey.toString());
+      expect(batch.compliance.routeDeviation).to.be.false;
+    });
+
+    it("Should raise a route deviation for a checkpoint outside the corridor", async () => {
+      await program.methods
+        .logCheckpoint(fix(utrecht), detailsHash, detailsCid)
+        .accounts({
+          batch: logisticsBatchPda,
+          callerProfile: producerProfilePda,
+          caller: producer.publicKey,
+          serviceBinding: null,
+          route: logisticsRoutePda,
+        })
+        .signers([producer])
+        .rpc();
+
+      const batch = await program.account.batch.fetch(logisticsBatchPda);
+      const [checkpoint, deviation] = batch.events.slice(-2);
+      expect(checkpoint.eventType).to.deep.equal({ checkpoint: {} });
+      expect(deviation.eventType).to.deep.equal({ routeDeviation: {} });
+      expect(deviation.location).to.deep.equal(utrecht);
+      expect(batch.compliance.routeDeviation).to.be.true;
+    });
+
+    it("Should record only the first deviation in the batch log", async () => {
+      await program.methods
+        .logCheckpoint(fix(utrecht), detailsHash, detailsCid)
+        .accounts({
+          batch: logisticsBatchPda,
+          callerProfile: producerProfilePda,
+          caller: producer.publicKey,
+          serviceBinding: null,
+          route: logisticsRoutePda,
+        })
+        .signers([producer])
+        .rpc();
+
+      const batch = await program.account.batch.fetch(logisticsBatchPda);
+      expect(batch.events[batch.events.length - 1].eventType).to.deep.equal({ checkpoint: {} });
+      expect(batch.events.filter((e) => e.eventType.routeDeviation !== undefined)).to.have.length(1);
+    });
+
+    it("Should require the route account once a route is defined", async () => {
+      try {
+        await program.methods
+          .logCheckpoint(fix(rotterdam), detailsHash, detailsCid)
+          .accounts({
+            batch: logisticsBatchPda,
+            callerProfile: producerProfilePda,
+            caller: producer.publicKey,
+            serviceBinding: null,
+            route: null,
+          })
+          .signers([producer])
+          .rpc();
+        expect.fail("Expected logCheckpoint to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("RouteAccountMissing");
+      }
+    });
+
+    it("Should reject updates from a warehouse the custodian has not bound", async () => {
+      try {
+        await program.methods
+          .logStorageUpdate(detailsHash, detailsCid)
+          .accounts({
+            batch: logisticsBatchPda,
+            callerProfile: warehouseProfilePda,
+            caller: warehouse.publicKey,
+            serviceBinding: null,
+            route: logisticsRoutePda,
+          })
+          .signers([warehouse])
+          .rpc();
+        expect.fail("Expected logStorageUpdate to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("NotCustodianOrProvider");
+      }
+    });
+
+    it("Should accept storage updates from a bound warehouse without moving ownership", async () => {
+      await program.methods
+        .bindServiceProvider()
+        .accounts({
+          serviceBinding: warehouseBindingPda,
+          batch: logisticsBatchPda,
+          providerProfile: warehouseProfilePda,
+          custodian: producer.publicKey,
+        })
+        .signers([producer])
+        .rpc();
+
+      await program.methods
+        .logStorageUpdate(detailsHash, detailsCid)
+        .accounts({
+          batch: logisticsBatchPda,
+          callerProfile: warehouseProfilePda,
+          caller: warehouse.publicKey,
+          serviceBinding: warehouseBindingPda,
+          route: logisticsRoutePda,
+        })
+        .signers([warehouse])
+        .rpc();
+
+      const batch = await program.account.batch.fetch(logisticsBatchPda);
+      const last = batch.events[batch.events.length - 1];
+      expect(last.eventType).to.deep.equal({ storageUpdate: {} });
+      expect(last.fromWallet.toString()).to.equal(warehouse.publicKey.toString());
+      expect(batch.currentOwner.toString()).to.equal(producer.publicKey.toString());
+    });
+
+    it("Should store a base16 CID in its base32 form", async () => {
+      const base16DetailsCid = "f0155122041ffd8b76afc92a65758fd9a080ae80421c196f251263b87cbfb6e567dda0879";
+
+      await program.methods
+        .logStorageUpdate(detailsHash, base16DetailsCid)
+        .accounts({
+          batch: logisticsBatchPda,
+          callerProfile: warehouseProfilePda,
+          caller: warehouse.publicKey,
+          serviceBinding: warehouseBindingPda,
+          route: logisticsRoutePda,
+        })
+        .signers([warehouse])
+        .rpc();
+
+      const batch = await program.account.batch.fetch(logisticsBatchPda);
+      expect(batch.events[batch.events.length - 1].detailsCid).to.equal(detailsCid);
+    });
+
+    it("Should give a transporter custody while the producer keeps title", async () => {
+      await program.methods
+        .transferCustody(transporter.publicKey, detailsHash, detailsCid)
+        .accounts({
+          batch: logisticsBatchPda,
+          ...(await handoverLegs(logisticsBatchPda)),
+          fromUserProfile: producerProfilePda,
+          toUserProfile: transporterProfilePda,
+          fromUser: producer.publicKey,
+          toUser: transporter.publicKey,
+        })
+        .signers([producer, transporter])
+        .rpc();
+
+      const batch = await program.account.batch.fetch(logisticsBatchPda);
+      expect(batch.currentCustodian.toString()).to.equal(transporter.publicKey.toString());
+      expect(batch.currentOwner.toString()).to.equal(producer.publicKey.toString());
+      expect(batch.status).to.deep.equal({ registered: {} });
+      expect(batch.handoverCount).to.equal(0);
+      expect(batch.events[batch.events.length - 1].eventType).to.deep.equal({ custodyTransfer: {} });
+    });
+
+    it("Should stop honouring a binding made by the previous custodian", async () => {
+      try {
+        await program.methods
+          .logStorageUpdate(detailsHash, detailsCid)
+          .accounts({
+            batch: logisticsBatchPda,
+            callerProfile: warehouseProfilePda,
+            caller: warehouse.publicKey,
+            serviceBinding: warehouseBindingPda,
+            route: logisticsRoutePda,
+          })
+          .signers([warehouse])
+          .rpc();
+        expect.fail("Expected logStorageUpdate to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("ConstraintSeeds");
+      }
+    });
+
+    it("Should let the new custodian bind the same warehouse afresh", async () => {
+      const [transporterBindingPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("service"), logisticsBatchPda.toBuffer(), transporter.publicKey.toBuffer(), warehouse.publicKey.toBuffer()],
+        program.programId
+      );
+
+      await program.methods
+        .bindServiceProvider()
+        .accounts({
+          serviceBinding: transporterBindingPda,
+          batch: logisticsBatchPda,
+          providerProfile: warehouseProfilePda,
+          custodian: transporter.publicKey,
+        })
+        .signers([transporter])
+        .rpc();
+
+      const binding = await program.account.serviceBinding.fetch(transporterBindingPda);
+      expect(binding.custodian.toString()).to.equal(transporter.publicKey.toString());
+    });
+
+    it("Should refuse a full handover while custody is held elsewhere", async () => {
+      try {
+        await program.methods
+          .logHandover(distributor.publicKey, detailsHash, detailsCid)
+          .accounts({
+            batch: logisticsBatchPda,
+            ...(await handoverLegs(logisticsBatchPda)),
+            fromUserProfile: producerProfilePda,
+            toUserProfile: distributorProfilePda,
+            fromUser: producer.publicKey,
+            toUser: distributor.publicKey,
+          })
+          .signers([producer, distributor])
+          .rpc();
+        expect.fail("Expected logHandover to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("NotCurrentCustodian");
+      }
+    });
+
+    it("Should refuse to transfer title along a disallowed role transition", async () => {
+      try {
+        await program.methods
+          .transferOwnership(consumer.publicKey, detailsHash)
+          .accounts({
+            batch: logisticsBatchPda,
+            fromUserProfile: producerProfilePda,
+            toUserProfile: consumerProfilePda,
+            fromUser: producer.publicKey,
+            toUser: consumer.publicKey,
+          })
+          .signers([producer, consumer])
+          .rpc();
+        expect.fail("Expected transferOwnership to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("InvalidRoleTransition");
+      }
+    });
+
+    it("Should transfer title without moving the goods", async () => {
+      await program.methods
+        .transferOwnership(distributor.publicKey, detailsHash)
+        .accounts({
+          batch: logisticsBatchPda,
+          fromUserProfile: producerProfilePda,
+          toUserProfile: distributorProfilePda,
+          fromUser: producer.publicKey,
+          toUser: distributor.publicKey,
+        })
+        .signers([producer, distributor])
+        .rpc();
+
+      const batch = await program.account.batch.fetch(logisticsBatchPda);
+      expect(batch.currentOwner.toString()).to.equal(distributor.publicKey.toString());
+      expect(batch.currentCustodian.toString()).to.equal(transporter.publicKey.toString());
+      expect(batch.events[batch.events.length - 1].eventType).to.deep.equal({ ownershipTransfer: {} });
+    });
+  });
+
+  describe("Shipment Plans", () => {
+    const shipmentBatchId = "SHIPMENT_BATCH";
+    const origin = { latE7: 519_225_000, lonE7: 44_791_700 };
+    const destination = { latE7: 523_676_000, lonE7: 49_041_000, radiusM: 10_000 };
+    let shipmentBatchPda: PublicKey;
+
+    before(async () => {
+      [shipmentBatchPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch"), producer.publicKey.toBuffer(), Buffer.from(shipmentBatchId)],
+        program.programId
+      );
+
+      await program.methods
+        .createBatch(shipmentBatchId, originDetails, metadataHash, metadataCid, metadataSchemaVersion, null)
+        .accounts({
+          batch: shipmentBatchPda,
+          product: productPda,
+          route: null,
+          userProfile: producerProfilePda,
+          user: producer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([producer])
+        .rpc();
+    });
+
+    it("Should let the crank mark an overdue shipment late", async () => {
+      await program.methods
+        .dispatchShipment(processor.publicKey, origin, destination, new BN(1))
+        .accounts({
+          shipmentPlan: shipmentPlanPda(shipmentBatchPda),
+          batch: shipmentBatchPda,
+          senderProfile: producerProfilePda,
+          sender: producer.publicKey,
+        })
+        .signers([producer])
+        .rpc();
+
+      await new Promise(resolve => setTimeout(resolve, 3000));
+
+      await program.methods
+        .settleOverdueShipment()
+        .accounts({
+          shipmentPlan: shipmentPlanPda(shipmentBatchPda),
+          batch: shipmentBatchPda,
+        })
+        .rpc();
+
+      const batch = await program.account.batch.fetch(shipmentBatchPda);
+      expect(batch.shipmentOpen).to.be.true;
+      expect(batch.shipmentOutcome).to.deep.equal({ late: {} });
+    });
+
+    it("Should only hand the batch to the planned receiver", async () => {
+      try {
+        await program.methods
+          .logHandover(distributor.publicKey, detailsHash, detailsCid)
+          .accounts({
+            batch: shipmentBatchPda,
+            ...(await handoverLegs(shipmentBatchPda)),
+            fromUserProfile: producerProfilePda,
+            toUserProfile: distributorProfilePda,
+            fromUser: producer.publicKey,
+            toUser: distributor.publicKey,
+          })
+          .signers([producer, distributor])
+          .rpc();
+        expect.fail("Expected logHandover to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("UnexpectedReceiver");
+      }
+    });
+
+    it("Should close the plan on receipt", async () => {
+      await program.methods
+        .logHandover(processor.publicKey, detailsHash, detailsCid)
+        .accounts({
+          batch: shipmentBatchPda,
+          ...(await handoverLegs(shipmentBatchPda)),
+          fromUserProfile: producerProfilePda,
+          toUserProfile: processorProfilePda,
+          fromUser: producer.publicKey,
+          toUser: processor.publicKey,
+        })
+        .signers([producer, processor])
+        .rpc();
+
+      const batch = await program.account.batch.fetch(shipmentBatchPda);
+      expect(batch.shipmentOpen).to.be.false;
+      expect(batch.shipmentOutcome).to.deep.equal({ late: {} });
+      expect(await provider.connection.getAccountInfo(shipmentPlanPda(shipmentBatchPda))).to.be.null;
+    });
+
+    it("Should record an on-time delivery", async () => {
+      await program.methods
+        .dispatchShipment(distributor.publicKey, origin, destination, new BN(3600))
+        .accounts({
+          shipmentPlan: shipmentPlanPda(shipmentBatchPda),
+          batch: shipmentBatchPda,
+          senderProfile: processorProfilePda,
+          sender: processor.publicKey,
+        })
+        .signers([processor])
+        .rpc();
+
+      await program.methods
+        .logHandover(distributor.publicKey, detailsHash, detailsCid)
+        .accounts({
+          batch: shipmentBatchPda,
+          ...(await handoverLegs(shipmentBatchPda)),
+          fromUserProfile: processorProfilePda,
+          toUserProfile: distributorProfilePda,
+          fromUser: processor.publicKey,
+          toUser: distributor.publicKey,
+        })
+        .signers([processor, distributor])
+        .rpc();
+
+      const batch = await program.account.batch.fetch(shipmentBatchPda);
+      expect(batch.shipmentOutcome).to.deep.equal({ onTime: {} });
+      expect(batch.currentOwner.toString()).to.equal(distributor.publicKey.toString());
+    });
+
+    it("Should only let the sender or admin cancel an open shipment", async () => {
+      await program.methods
+        .dispatchShipment(retailer.publicKey, origin, destination, new BN(3600))
+        .accounts({
+          shipmentPlan: shipmentPlanPda(shipmentBatchPda),
+          batch: shipmentBatchPda,
+          senderProfile: distributorProfilePda,
+          sender: distributor.publicKey,
+        })
+        .signers([distributor])
+        .rpc();
+
+      try {
+        await program.methods
+          .cancelShipment()
+          .accounts({
+            shipmentPlan: shipmentPlanPda(shipmentBatchPda),
+            batch: shipmentBatchPda,
+            sender: distributor.publicKey,
+            caller: retailer.publicKey,
+          })
+          .signers([retailer])
+          .rpc();
+        expect.fail("Expected cancelShipment to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("InvalidRole");
+      }
+    });
+
+    it("Should release the receiver lock when the sender cancels", async () => {
+      await program.methods
+        .cancelShipment()
+        .accounts({
+          shipmentPlan: shipmentPlanPda(shipmentBatchPda),
+          batch: shipmentBatchPda,
+          sender: distributor.publicKey,
+          caller: distributor.publicKey,
+        })
+        .signers([distributor])
+        .rpc();
+
+      const batch = await program.account.batch.fetch(shipmentBatchPda);
+      expect(batch.shipmentOpen).to.be.false;
+      expect(batch.shipmentOutcome).to.deep.equal({ cancelled: {} });
+      expect(batch.events[batch.events.length - 1].eventType).to.deep.equal({ shipmentCancelled: {} });
+      expect(await provider.connection.getAccountInfo(shipmentPlanPda(shipmentBatchPda))).to.be.null;
+    });
+  });
+
+  describe("Expiry", () => {
+    const expiringBatchId = "EXPIRING_BATCH";
+    const flaggedExpiringBatchId = "EXPIRING_FLAGGED";
+    let expiringBatchPda: PublicKey;
+    let flaggedExpiringBatchPda: PublicKey;
+
+    before(async () => {
+      [expiringBatchPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch"), producer.publicKey.toBuffer(), Buffer.from(expiringBatchId)],
+        program.programId
+      );
+      [flaggedExpiringBatchPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch"), producer.publicKey.toBuffer(), Buffer.from(flaggedExpiringBatchId)],
+        program.programId
+      );
+
+      const now = Math.floor(Date.now() / 1000);
+      for (const [id, pda] of [[expiringBatchId, expiringBatchPda], [flaggedExpiringBatchId, flaggedExpiringBatchPda]] as const) {
+        await program.methods
+          .createBatch(
+            id,
+            { ...originDetails, productionDate: new BN(now - 3600), expiryDate: new BN(now + 3) },
+            metadataHash,
+            metadataCid,
+            metadataSchemaVersion,
+            null
+          )
+          .accounts({
+            batch: pda,
+            product: productPda,
+            route: null,
+            userProfile: producerProfilePda,
+            user: producer.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([producer])
+          .rpc();
+      }
+
+      await program.methods
+        .flagBatch("Packaging damage reported")
+        .accounts({
+          batch: flaggedExpiringBatchPda,
+          callerProfile: regulatorProfilePda,
+          caller: regulator.publicKey,
+          systemConfig: systemConfigPda,
+        })
+        .signers([regulator])
+        .rpc();
+
+      await new Promise(resolve => setTimeout(resolve, 5000));
+    });
+
+    it("Should refuse to hand over an expired batch", async () => {
+      try {
+        await program.methods
+          .logHandover(processor.publicKey, detailsHash, detailsCid)
+          .accounts({
+            batch: expiringBatchPda,
+            ...(await handoverLegs(expiringBatchPda)),
+            fromUserProfile: producerProfilePda,
+            toUserProfile: processorProfilePda,
+            fromUser: producer.publicKey,
+            toUser: processor.publicKey,
+          })
+          .signers([producer, processor])
+          .rpc();
+        expect.fail("Expected logHandover to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("BatchExpired");
+      }
+    });
+
+    it("Should let anyone mark the batch expired", async () => {
+      await program.methods
+        .markExpired()
+        .accounts({
+          batch: expiringBatchPda,
+          caller: oracle.publicKey,
+        })
+        .signers([oracle])
+        .rpc();
+
+      const batch = await program.account.batch.fetch(expiringBatchPda);
+      expect(batch.status).to.deep.equal({ expired: {} });
+      expect(batch.events[batch.events.length - 1].eventType).to.deep.equal({ expiry: {} });
+    });
+
+    it("Should still allow an expired batch to be destroyed", async () => {
+      await program.methods
+        .markDestroyed(metadataHash, detailsCid)
+        .accounts({
+          batch: expiringBatchPda,
+          callerProfile: producerProfilePda,
+          caller: producer.publicKey,
+        })
+        .signers([producer])
+        .rpc();
+
+      const batch = await program.account.batch.fetch(expiringBatchPda);
+      expect(batch.status).to.deep.equal({ closed: {} });
+      expect(batch.closureReason).to.deep.equal({ destroyed: {} });
+    });
+
+    it("Should keep a flagged batch under review when it expires", async () => {
+      await program.methods
+        .markExpired()
+        .accounts({
+          batch: flaggedExpiringBatchPda,
+          caller: oracle.publicKey,
+        })
+        .signers([oracle])
+        .rpc();
+
+      const batch = await program.account.batch.fetch(flaggedExpiringBatchPda);
+      expect(batch.status).to.deep.equal({ flagged: {} });
+      expect(batch.preFlagStatus).to.deep.equal({ expired: {} });
+    });
+
+    it("Should not let the crank expire a batch stored away from its PDA", async () => {
+      try {
+        await program.methods
+          .markExpired()
+          .accounts({
+            batch: spoofedBatchPda,
+            caller: oracle.publicKey,
+          })
+          .signers([oracle])
+          .rpc();
+        expect.fail("Expected markExpired to fail");
+      } catch (err) {
+        expect(err.toString()).to.include("ConstraintSeeds");
+      }
+    });
+  });
+
+  describe("Shelf Life", () => {
+    const shelfLifeBatchId = "SHELF_LIFE_BATCH";
+    let shelfLifeBatchPda: PublicKey;
+
+    before(async () => {
+      [shelfLifeBatchPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch"), producer.publicKey.toBuffer(), Buffer.from(shelfLifeBatchId)],
+        program.programId
+      );
+
+      await program.methods
+        .createBatch(shelfLifeBatchId, originDetails, metadataHash, metadataCid, metadataSchemaVersion, null)
+        .accounts({
+          batch: shelfLifeBatchPda,
+          product: productPda,
+          route: null,
+          userProfile: producerProfilePda,
+          user: producer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([producer])
+        .rpc();
+    });
+
+    it("Should consume shelf life at the rate of the matching temperature band", async () => {
+      const before = await program.account.batch.fetch(shelfLifeBatchPda);
+      const timestamp = before.shelfLifeUpdatedAt.toNumber() + 120;
+
+      await program.methods
+        .updateIotSummary({ ...iotSummary, timestamp, avgTempCentiC: 500 }, iotHash, iotCid)
+        .accounts({
+          batch: shelfLifeBatchPda,
+          oracle: oracle.publicKey,
+          route: null,
+          systemConfig: systemConfigPda,
+        })
+        .signers([oracle])
+        .rpc();
+
+      // 120 s in the 4-8 °C band at 3x the nominal rate.
+      const batch = await program.account.batch.fetch(shelfLifeBatchPda);
+      expect(batch.remainingShelfLifeSeconds.toNumber()).to.equal(
+        before.remainingShelfLifeSeconds.toNumber() - 360
+      );
+      expect(batch.shelfLifeUpdatedAt.toNumber()).to.equal(timestamp);
+      expect(batch.effectiveExpiry.toNumber()).to.equal(
+        timestamp + batch.remainingShelfLifeSeconds.toNumber()
+      );
+    });
+
+    it("Should follow the declared expiry for products without a shelf-life model", async () => {
+      const [teaPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("product"), Buffer.from("LOOSE-LEAF-TEA")],
+        program.programId
+      );
+      const otherBatchId = "SHELF_LIFE_PLAIN";
+      const [otherBatchPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch"), producer.publicKey.toBuffer(), Buffer.from(otherBatchId)],
+        program.programId
+      );
+
+      await program.methods
+        .createBatch(
+          otherBatchId,
+          { ...originDetails, product: teaPda },
+          metadataHash,
+          metadataCid,
+          metadataSchemaVersion,
+          null
+        )
+        .accounts({
+          batch: otherBatchPda,
+          product: teaPda,
+          route: null,
+          userProfile: producerProfilePda,
+          user: producer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([producer])
+        .rpc();
+
+      const batch = await program.account.batch.fetch(otherBatchPda);
+      expect(batch.degradation).to.have.length(0);
+      expect(batch.effectiveExpiry.toNumber()).to.equal(originDetails.expiryDate.toNumber());
+      expect(
+        batch.shelfLifeUpdatedAt.toNumber() + batch.remainingShelfLifeSeconds.toNumber()
+      ).to.equal(originDetails.expiryDate.toNumber());
+    });
+  });
+
+  describe("GS1 Identifiers", () => {
+    const gtin = "4006381333931";
+    const lot = "L2026-10A";
+    const sscc = "106141411234567897";
+    const gs1BatchId = "GS1_BATCH";
+    const [gtinProductPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("product"), Buffer.from(gtin)],
+      program.programId
+    );
+    // Lookups are keyed by the GTIN-14 form.
+    const lotLookupPda = (lotNumber: string) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("lot"), Buffer.from(gtin.padStart(14, "0")), Buffer.from(lotNumber)],
+        program.programId
+      )[0];
+    let gs1BatchPda: PublicKey;
+
+    before(async () => {
+      [gs1BatchPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch"), producer.publicKey.toBuffer(), Buffer.from(gs1BatchId)],
+        program.programId
+      );
+
+      await program.methods
+        .registerProduct(gtin, {
+          name: "Highlighter Yellow",
+          category: "Stationery",
+          brandOwner: producer.publicKey,
+          defaultThreshold: {
+            maxTempCentiC: 4_000,
+            maxHumidityBps: 9_000,
+            maxBreachDuration: 1_800,
+            excursionShelfLifeFactor: 0,
+          },
+          shelfLifeSeconds: new BN(0),
+          degradation: [],
+        })
+        .accounts({
+          admin: admin.publicKey,
+          systemConfig: systemConfigPda,
+        })
+        .signers([admin])
+        .rpc();
+
+      await program.methods
+        .createBatch(gs1BatchId, { ...originDetails, product: gtinProductPda }, metadataHash, metadataCid, metadataSchemaVersion, null)
+        .accounts({
+          batch: gs1BatchPda,
+          product: gtinProductPda,
+          route: null,
+          userProfile: producerProfilePda,
+          user: producer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([producer])
+        .rpc();
+    });
+
+    it("Should resolve a GTIN and lot number to the batch", async () => {
+      await program.methods
+        .assignGs1Lot(lot)
+        .accounts({
+          lotLookup: lotLookupPda(lot),
+          batch: gs1BatchPda,
+          product: gtinProductPda,
+          producer: producer.publicKey,
+        })
+        .signers([producer])
+        .rpc();
+
+      const lookup = await program.account.lotLookup.fetch(lotLookupPda(lot));
+      expect(lookup.gtin).to.equal("04006381333931");
+      expect(lookup.batch.toString()).to.equal(gs1BatchPda.toString());
+
+      const batch = await program.account.batch.fetch(gs1BatchPda);
+      expect(batch.gs1Lot).to.equal(lot);
+    });
+
+    it("Should assign only one lot number per batch", async () => {
+      try {
+        await program.methods
+          .assignGs1Lot("L2026-10B")
+          .accounts({
+            lotLookup: lotLookupPda("L2026-10B"),
+            batch: gs1BatchPda,
+            product: gtinProductPda,
+            producer: producer.publicKey,
+          })
+          .signers([producer])
+          .rpc();
+        expect.fail("Should have thrown an error");
+      } catch (error) {
+        expect(error.message).to.include("LotAlreadyAssigned");
+      }
+    });
+
+    it("Should only let the brand owner assign lots under its GTIN", async () => {
+      const otherGtin = "00012345600012";
+      const otherBatchId = "GS1_OTHER_BRAND";
+      const [otherProductPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("product"), Buffer.from(otherGtin)],
+        program.programId
+      );
+      const [otherBatchPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch"), producer.publicKey.toBuffer(), Buffer.from(otherBatchId)],
+        program.programId
+      );
+
+      await program.methods
+        .registerProduct(otherGtin, {
+          name: "Highlighter Pink",
+          category: "Stationery",
+          brandOwner: processor.publicKey,
+          defaultThreshold: {
+            maxTempCentiC: 4_000,
+            maxHumidityBps: 9_000,
+            maxBreachDuration: 1_800,
+            excursionShelfLifeFactor: 0,
+          },
+          shelfLifeSeconds: new BN(0),
+          degradation: [],
+        })
+        .accounts({
+          admin: admin.publicKey,
+          systemConfig: systemConfigPda,
+        })
+        .signers([admin])
+        .rpc();
+
+      await program.methods
+        .createBatch(otherBatchId, { ...originDetails, product: otherProductPda }, metadataHash, metadataCid, metadataSchemaVersion, null)
+        .accounts({
+          batch: otherBatchPda,
+          product: otherProductPda,
+          route: null,
+          userProfile: producerProfilePda,
+          user: producer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([producer])
+        .rpc();
+
+      try {
+        await program.methods
+          .assignGs1Lot(lot)
+          .accounts({
+            lotLookup: PublicKey.findProgramAddressSync(
+              [Buffer.from("lot"), Buffer.from(otherGtin), Buffer.from(lot)],
+              program.programId
+            )[0],
+            batch: otherBatchPda,
+            product: otherProductPda,
+            producer: producer.publicKey,
+          })
+          .signers([producer])
+          .rpc();
+        expect.fail("Should have thrown an error");
+      } catch (error) {
+        expect(error.message).to.include("NotBrandOwner");
+      }
+    });
+
+    it("Should reject lots for products whose SKU is not a GTIN", async () => {
+      try {
+        await program.methods
+          .assignGs1Lot(lot)
+          .accounts({
+            lotLookup: PublicKey.findProgramAddressSync(
+              [Buffer.from("lot"), Buffer.from(""), Buffer.from(lot)],
+              program.programId
+            )[0],
+            batch: batchPda,
+            product: productPda,
+            producer: producer.publicKey,
+          })
+          .signers([producer])
+          .rpc();
+        expect.fail("Should have thrown an error");
+      } catch (error) {
+        expect(error.message).to.include("InvalidGtin");
+      }
+    });
+
+    it("Should register an SSCC-labelled logistic unit for the custodian", async () => {
+      const [logisticUnitPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("sscc"), Buffer.from(sscc)],
+        program.programId
+      );
+
+      await program.methods
+        .registerLogisticUnit(sscc)
+        .accounts({
+          logisticUnit: logisticUnitPda,
+          batch: gs1BatchPda,
+          custodian: producer.publicKey,
+        })
+        .signers([producer])
+        .rpc();
+
+      const unit = await program.account.logisticUnit.fetch(logisticUnitPda);
+      expect(unit.batch.toString()).to.equal(gs1BatchPda.toString());
+      expect(unit.registeredBy.toString()).to.equal(producer.publicKey.toString());
+    });
+
+    it("Should reject an SSCC with a wrong check digit", async () => {
+      const badSscc = "106141411234567890";
+      try {
+        await program.methods
+          .registerLogisticUnit(badSscc)
+          .accounts({
+            logisticUnit: PublicKey.findProgramAddressSync(
+              [Buffer.from("sscc"), Buffer.from(badSscc)],
+              program.programId
+            )[0],
+            batch: gs1BatchPda,
+            custodian: producer.publicKey,
+          })
+          .signers([producer])
+          .rpc();
+        expect.fail("Should have thrown an error");
+      } catch (error) {
+        expect(error.message).to.include("InvalidSscc");
+      }
+    });
+  });
+
+  describe("System Integration Tests", () => {
+    it("Should handle complete supply chain flow", async () => {
+      const integrationBatchId = "INTEGRATION_BATCH";
+      const [integrationBatchPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("batch"), producer.publicKey.toBuffer(), Buffer.from(integrationBatchId)],
+        program.programId
+      );
+
+      // 1. Create batch
+      await program.methods
+        .createBatch(integrationBatchId, originDetails, metadataHash, metadataCid, metadataSchemaVersion, null)
+        .accounts({
+          batch: integrationBatchPda,
+          product: productPda,
+          route: null,
+          userProfile: producerProfilePda,
+          user: producer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([producer])
+        .rpc();
+
+      let batch = await program.account.batch.fetch(integrationBatchPda);
+      expect(batch.status).to.deep.equal({ registered: {} });
+
+      // 2. Update IoT summary
+      await program.methods
+        .updateIotSummary(iotSummary, iotHash, iotCid)
+        .accounts({
+          batch: integrationBatchPda,
+          oracle: oracle.publicKey,
+          route: null,
+          systemConfig: systemConfigPda,
+        })
+        .signers([oracle])
+        .rpc();
+
+      // 3. Check compliance
+      await program.methods
+        .checkCompliance()
+        .accounts({
+          batch: integrationBatchPda,
+          callerProfile: regulatorProfilePda,
+          caller: regulator.publicKey,
+          systemConfig: systemConfigPda,
+        })
+        .signers([regulator])
+        .rpc();
+
+      // 4. Issue certification
+      const [integrationCertPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("cert"), integrationBatchPda.toBuffer(), Buffer.from("organic")],
+        program.programId
+      );
+
+      await program.methods
+        .issueCertification("organic", certHash, certCid)
+        .accounts({
+          certification: integrationCertPda,
+          batch: integrationBatchPda,
+          issuerProfile: regulatorProfilePda,
           issuer: regulator.publicKey,
           systemProgram: SystemProgram.programId,
         })
@@ -562,6 +3041,7 @@ describe("Supply Chain Contracts", () => {
         .logHandover(processor.publicKey, detailsHash, detailsCid)
         .accounts({
           batch: integrationBatchPda,
+          ...(await handoverLegs(integrationBatchPda)),
           fromUserProfile: producerProfilePda,
           toUserProfile: processorProfilePda,
           fromUser: producer.publicKey,
@@ -579,6 +3059,7 @@ describe("Supply Chain Contracts", () => {
         .logHandover(distributor.publicKey, detailsHash, detailsCid)
         .accounts({
           batch: integrationBatchPda,
+          ...(await handoverLegs(integrationBatchPda)),
           fromUserProfile: processorProfilePda,
           toUserProfile: distributorProfilePda,
           fromUser: processor.publicKey,
@@ -596,6 +3077,7 @@ describe("Supply Chain Contracts", () => {
         .logHandover(retailer.publicKey, detailsHash, detailsCid)
         .accounts({
           batch: integrationBatchPda,
+          ...(await handoverLegs(integrationBatchPda)),
           fromUserProfile: distributorProfilePda,
           toUserProfile: retailerProfilePda,
           fromUser: distributor.publicKey,
@@ -613,6 +3095,7 @@ describe("Supply Chain Contracts", () => {
         .logHandover(consumer.publicKey, detailsHash, detailsCid)
         .accounts({
           batch: integrationBatchPda,
+          ...(await handoverLegs(integrationBatchPda)),
           fromUserProfile: retailerProfilePda,
           toUserProfile: consumerProfilePda,
           fromUser: retailer.publicKey,
@@ -675,14 +3158,14 @@ describe("Supply Chain Contracts", () => {
       expect(batch.metadataHash).to.deep.equal(metadataHash);
       
       // Verify origin details
-      expect(batch.originDetails.productType).to.equal(originDetails.productType);
+      expect(batch.originDetails.product.toString()).to.equal(productPda.toString());
       expect(batch.originDetails.quantity.toNumber()).to.equal(originDetails.quantity.toNumber());
-      expect(batch.originDetails.weight).to.equal(originDetails.weight);
+      expect(batch.originDetails.weightGrams.toNumber()).to.equal(originDetails.weightGrams.toNumber());
       
       // Verify IoT summary
       expect(batch.iotSummary.timestamp).to.equal(iotSummary.timestamp);
-      expect(batch.iotSummary.minTemp).to.equal(iotSummary.minTemp);
-      expect(batch.iotSummary.maxTemp).to.equal(iotSummary.maxTemp);
+      expect(batch.iotSummary.minTempCentiC).to.equal(iotSummary.minTempCentiC);
+      expect(batch.iotSummary.maxTempCentiC).to.equal(iotSummary.maxTempCentiC);
       expect(batch.iotCid).to.equal(iotCid);
       
       // Verify compliance
@@ -694,5 +3177,17 @@ describe("Supply Chain Contracts", () => {
         expect(batch.events[i].timestamp.toNumber()).to.be.greaterThanOrEqual(batch.events[i - 1].timestamp.toNumber());
       }
     });
+
+    it("Should chain every logged event into the history root", async () => {
+      const batch = await program.account.batch.fetch(batchPda);
+
+      let root = Buffer.alloc(32);
+      for (const event of batch.events) {
+        const encoded = program.coder.types.encode("Event", event);
+        root = createHash("sha256").update(root).update(encoded).digest();
+      }
+
+      expect(Array.from(root)).to.deep.equal(batch.historyRoot);
+    });
   });
 });
\ No newline at end of file